@@ -0,0 +1,279 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::vec::Vec;
+
+use futures_core::{ready, Stream};
+use futures_io::AsyncRead;
+
+use crate::decoder::PartDecoder;
+use crate::Format;
+
+const CHUNK: usize = 8 * 1024;
+
+/// Reads parts from a `futures_io::AsyncRead` encoded in the same framing as `Buffer`
+///
+/// This is the asynchronous counterpart of `BufferReader`: parts are yielded as a `Stream`,
+/// checksums are always verified, malformed framing is reported as an `io::Error` of kind
+/// `InvalidData` wrapping a `SplitBufferError`, and a stream that ends within the header or a
+/// part as `UnexpectedEof`. The stream ends after the first error.
+#[derive(Debug)]
+pub struct AsyncBufferReader<R> {
+    inner: R,
+    decoder: PartDecoder,
+    buffer: Vec<u8>,
+    start: usize,
+    end: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufferReader<R> {
+    /// Create a reader, using the `Format` recorded in the header or the default `Format` if the
+    /// stream has no header.
+    ///
+    /// A stream that starts with the magic bytes but has an unsupported header is read as
+    /// headerless, and the header error is returned if its first part cannot be read.
+    pub fn new(inner: R) -> Self {
+        Self::from_decoder(inner, PartDecoder::detect())
+    }
+
+    /// Create a reader for a stream encoded with the given `Format`.
+    ///
+    /// If the format has a header, the header in the stream must match it.
+    pub fn with_format<F: Into<Format>>(inner: R, format: F) -> Self {
+        Self::from_decoder(inner, PartDecoder::new(format.into()))
+    }
+
+    fn from_decoder(inner: R, decoder: PartDecoder) -> Self {
+        AsyncBufferReader {
+            inner,
+            decoder,
+            buffer: Vec::new(),
+            start: 0,
+            end: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Reject parts longer than `max` bytes with `SplitBufferError::PartTooLong`.
+    pub fn with_max_part_len(mut self, max: usize) -> Self {
+        self.decoder.set_max_part_len(max);
+        self
+    }
+
+    /// Get the `Format` parts are read in
+    ///
+    /// When the format is detected from the header, this is only known once the first part has
+    /// been polled.
+    pub fn format(&self) -> Format {
+        self.decoder.format()
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    ///
+    /// Reading from it directly will corrupt the framing.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Get the underlying reader.
+    ///
+    /// Bytes that have been read ahead of the last part are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Decode the next part from the bytes read so far, or return `None` if more are needed.
+    fn decode(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let bytes = &self.buffer[self.start..self.end];
+        let header = match self.decoder.read_header(bytes, self.eof)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        self.start += header;
+        let bytes = &self.buffer[self.start..self.end];
+        match self.decoder.read_part(bytes)? {
+            Some((range, next)) => {
+                let part = bytes[range].to_vec();
+                self.start += next;
+                Ok(Some(part))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Make room for at least `CHUNK` more bytes after those not yet decoded.
+    fn reserve(&mut self) {
+        if self.start == self.end {
+            self.start = 0;
+            self.end = 0;
+        } else if self.start > self.buffer.len() / 2 {
+            self.buffer.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+        if self.buffer.len() < self.end + CHUNK {
+            self.buffer.resize(self.end + CHUNK, 0);
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncBufferReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            match this.decode() {
+                Ok(Some(part)) => return Poll::Ready(Some(Ok(part))),
+                Ok(None) if this.eof => {
+                    this.done = true;
+                    if this.start < this.end {
+                        let err = match this.decoder.take_header_error() {
+                            Some(header_error) => header_error.into(),
+                            None => io::ErrorKind::UnexpectedEof.into(),
+                        };
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+                Ok(None) => {
+                    this.reserve();
+                    let buffer = &mut this.buffer[this.end..];
+                    match ready!(Pin::new(&mut this.inner).poll_read(cx, buffer)) {
+                        Ok(0) => this.eof = true,
+                        Ok(read) => this.end += read,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => {
+                            this.done = true;
+                            return Poll::Ready(Some(Err(e)));
+                        }
+                    }
+                }
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::stream::StreamExt;
+
+    use super::*;
+    use crate::testing::{formats, parts, split_buffer_error};
+    use crate::{Buffer, LengthEncoding, SplitBufferError};
+
+    /// Reader that returns one byte per read, and `Pending` before every read
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        pending: bool,
+    }
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let len = self.bytes.len().min(buf.len()).min(1);
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes = &self.bytes[len..];
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    fn trickle(bytes: &[u8]) -> Trickle<'_> {
+        Trickle { bytes, pending: false }
+    }
+
+    fn read_all<R: AsyncRead + Unpin>(
+        reader: &mut AsyncBufferReader<R>,
+    ) -> Vec<io::Result<Vec<u8>>> {
+        block_on(reader.collect())
+    }
+
+    #[test]
+    fn read_byte_at_a_time() {
+        for format in formats() {
+            let buffer = Buffer::build_with_format(parts(), format);
+
+            let mut reader = AsyncBufferReader::with_format(trickle(buffer.as_bytes()), format);
+            let read: io::Result<Vec<_>> = read_all(&mut reader).into_iter().collect();
+            assert_eq!(read.unwrap(), parts(), "{:?}", format);
+
+            if format.has_header() || format == Format::default() {
+                let mut reader = AsyncBufferReader::new(trickle(buffer.as_bytes()));
+                let read: io::Result<Vec<_>> = read_all(&mut reader).into_iter().collect();
+                assert_eq!(read.unwrap(), parts(), "{:?}", format);
+                assert_eq!(reader.format(), format);
+            }
+        }
+    }
+
+    #[test]
+    fn read_truncated() {
+        for format in formats() {
+            let buffer = Buffer::build_with_format(parts(), format);
+            let bytes = buffer.as_bytes();
+            for &len in &[1, format.header_len() + 1, bytes.len() - 1] {
+                let mut reader = AsyncBufferReader::with_format(trickle(&bytes[..len]), format);
+                let err = read_all(&mut reader).pop().unwrap().unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn read_corrupted() {
+        let format = Format::new(LengthEncoding::Varint).with_trailing_lengths(true);
+        let mut bytes = Buffer::build_with_format(parts(), format).into_inner();
+        let end = bytes.len() - 1;
+        bytes[end] = 0x82;
+
+        let mut reader = AsyncBufferReader::with_format(trickle(&bytes), format);
+        let mut read = read_all(&mut reader);
+        let err = read.pop().unwrap().unwrap_err();
+        assert_eq!(split_buffer_error(&err), &SplitBufferError::LengthMismatch { offset: 268 });
+        assert_eq!(read.len(), 4);
+        assert!(block_on(reader.next()).is_none());
+    }
+
+    #[test]
+    fn read_unsupported_header() {
+        let mut bytes =
+            Buffer::build_with_format(parts(), Format::default().with_header(true)).into_inner();
+        bytes[4] = 2;
+        let mut reader = AsyncBufferReader::new(trickle(&bytes));
+        let mut read = read_all(&mut reader);
+        assert_eq!(read.len(), 1);
+        let err = read.pop().unwrap().unwrap_err();
+        assert_eq!(split_buffer_error(&err), &SplitBufferError::UnsupportedVersion { version: 2 });
+
+        // Too short for a header, and truncated as a headerless stream.
+        let mut reader = AsyncBufferReader::new(trickle(b"SBUF"));
+        let err = read_all(&mut reader).pop().unwrap().unwrap_err();
+        assert_eq!(split_buffer_error(&err), &SplitBufferError::InvalidHeader);
+
+        let mut reader = AsyncBufferReader::new(trickle(&[]));
+        assert!(read_all(&mut reader).is_empty());
+    }
+}
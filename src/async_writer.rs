@@ -0,0 +1,177 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::vec::Vec;
+
+use futures_core::ready;
+use futures_io::AsyncWrite;
+use futures_sink::Sink;
+
+use crate::writer::check_part_len;
+use crate::{Format, WriteTotals};
+
+const HIGH_WATER_MARK: usize = 64 * 1024;
+
+/// Writes parts to a `futures_io::AsyncWrite` in the same framing as `Buffer`
+///
+/// This is the asynchronous counterpart of `BufferWriter`, accepting parts as a `Sink`. Parts are
+/// encoded into an internal buffer that is written out once it grows large and when the sink is
+/// flushed. Close the sink so that the header is also written for buffers without parts.
+#[derive(Debug)]
+pub struct AsyncBufferWriter<W> {
+    inner: W,
+    format: Format,
+    totals: WriteTotals,
+    started: bool,
+    buffer: Vec<u8>,
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncBufferWriter<W> {
+    /// Create a writer using the default `Format`.
+    pub fn new(inner: W) -> Self {
+        Self::with_format(inner, Format::default())
+    }
+
+    /// Create a writer using the given `Format`.
+    pub fn with_format<F: Into<Format>>(inner: W, format: F) -> Self {
+        AsyncBufferWriter {
+            inner,
+            format: format.into(),
+            totals: WriteTotals::default(),
+            started: false,
+            buffer: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Get the `Format` parts are written in
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get the number of parts and bytes accepted so far, including those not yet written out.
+    pub fn totals(&self) -> WriteTotals {
+        self.totals
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    ///
+    /// Writing to it directly will corrupt the framing.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Get the underlying writer.
+    ///
+    /// Parts that have not been flushed are lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let len = self.buffer.len();
+        self.format.write_header(&mut self.buffer);
+        self.totals.bytes += (self.buffer.len() - len) as u64;
+    }
+
+    /// Write out the internal buffer.
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.buffer.len() {
+            match ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buffer[self.written..])) {
+                Ok(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Ok(written) => self.written += written,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        self.buffer.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, W: AsyncWrite + Unpin> Sink<&'a [u8]> for AsyncBufferWriter<W> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.buffer.len() < HIGH_WATER_MARK {
+            return Poll::Ready(Ok(()));
+        }
+        this.poll_write_buffer(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, part: &'a [u8]) -> io::Result<()> {
+        let this = self.get_mut();
+        check_part_len(this.format, part.len())?;
+        this.start();
+        this.format.write_part(&mut this.buffer, part);
+        this.totals.parts += 1;
+        this.totals.bytes += this.format.encoded_len(part.len()) as u64;
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.start();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::sink::SinkExt;
+
+    use super::*;
+    use crate::testing::{formats, parts};
+    use crate::Buffer;
+
+    #[test]
+    fn write_matches_buffer() {
+        for format in formats() {
+            let mut writer = AsyncBufferWriter::with_format(Vec::new(), format);
+            block_on(async {
+                for part in &parts() {
+                    writer.send(&part[..]).await.unwrap();
+                }
+                writer.close().await.unwrap();
+            });
+
+            let expected = Buffer::build_with_format(parts(), format);
+            assert_eq!(writer.get_ref(), expected.as_bytes());
+            assert_eq!(
+                writer.totals(),
+                WriteTotals { parts: parts().len(), bytes: expected.as_bytes().len() as u64 }
+            );
+        }
+    }
+
+    #[test]
+    fn close_writes_header() {
+        let format = Format::default().with_header(true);
+        let mut writer = AsyncBufferWriter::with_format(Vec::new(), format);
+        block_on(writer.close()).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            Buffer::build_with_format(Vec::<&[u8]>::new(), format).into_inner()
+        );
+    }
+}
@@ -0,0 +1,282 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::ops::Range;
+
+#[cfg(feature = "alloc")]
+use crate::Buffer;
+use crate::{BufferIterator, Format, SplitBufferError, TryIter};
+
+/// Borrowed view over the parts of an encoded buffer
+///
+/// This allows iterating bytes that live outside of a `Buffer`, such as a memory map or a
+/// network read buffer, without copying them.
+#[derive(Clone, Copy, Debug)]
+pub struct BufferRef<'a> {
+    data: &'a [u8],
+    format: Format,
+    len: usize,
+    index: Option<&'a [usize]>,
+}
+
+impl<'a> BufferRef<'a> {
+    /// Create a view over encoded bytes, validating the framing.
+    ///
+    /// The `Format` recorded in the header is used, or the default `Format` if there is no
+    /// header.
+    ///
+    /// A headerless buffer can start with the magic bytes of a header if its first part is long
+    /// enough, so bytes with an unreadable header are also tried without one. If that fails too,
+    /// the header error is returned.
+    pub fn from_slice(bytes: &'a [u8]) -> Result<Self, SplitBufferError> {
+        match Format::detect(bytes) {
+            Ok(format) => Self::from_slice_with_format(bytes, format),
+            Err(e) => Self::from_slice_with_format(bytes, Format::default()).map_err(|_| e),
+        }
+    }
+
+    /// Create a view over bytes encoded with the given `Format`, validating the framing.
+    ///
+    /// If the format has a header, the header in `bytes` must match it.
+    pub fn from_slice_with_format<F: Into<Format>>(
+        bytes: &'a [u8],
+        format: F,
+    ) -> Result<Self, SplitBufferError> {
+        let format = format.into();
+        format.check_header(bytes)?;
+        let len = count_parts(bytes, format)?;
+        Ok(BufferRef { data: bytes, format, len, index: None })
+    }
+
+    /// Create a view over bytes without validating the framing.
+    ///
+    /// # Safety
+    ///
+    /// `bytes` must be a correctly framed buffer with a header or in the default `Format`.
+    pub unsafe fn from_slice_unchecked(bytes: &'a [u8]) -> Self {
+        Self::from_slice_with_format_unchecked(bytes, Format::detect(bytes).unwrap_or_default())
+    }
+
+    /// Create a view over bytes encoded with the given `Format` without validating the framing.
+    ///
+    /// # Safety
+    ///
+    /// `bytes` must be a correctly framed buffer in `format`.
+    pub unsafe fn from_slice_with_format_unchecked<F: Into<Format>>(
+        bytes: &'a [u8],
+        format: F,
+    ) -> Self {
+        let format = format.into();
+        let len = BufferIterator::new(bytes, format, usize::MAX, None).count();
+        BufferRef { data: bytes, format, len, index: None }
+    }
+
+    pub(crate) fn from_raw_parts(
+        data: &'a [u8],
+        format: Format,
+        len: usize,
+        index: Option<&'a [usize]>,
+    ) -> Self {
+        BufferRef { data, format, len, index }
+    }
+
+    /// Get the `Format` used to encode the buffer
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get the underlying bytes, including the header if the format has one
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Get the number of parts.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the buffer has no parts.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the parts of the buffer.
+    pub fn iter(&self) -> BufferIterator<'a> {
+        BufferIterator::new(self.data, self.format, self.len, self.index)
+    }
+
+    /// Iterate over the parts of the buffer, yielding an error if the framing is malformed or a
+    /// checksum does not match.
+    ///
+    /// Iteration stops after the first error.
+    pub fn try_iter(&self) -> TryIter<'a> {
+        TryIter { inner: self.iter() }
+    }
+
+    /// Check that every part of the buffer is framed correctly and matches its checksum.
+    pub fn validate(&self) -> Result<(), SplitBufferError> {
+        count_parts(self.data, self.format).map(|_| ())
+    }
+
+    /// Get the part at `index`.
+    ///
+    /// This walks the buffer from the start unless the view borrows an index from a `Buffer`.
+    pub fn get(&self, index: usize) -> Option<&'a [u8]> {
+        let (range, _) = self.span(index)?;
+        Some(&self.data[range])
+    }
+
+    /// Find the part at `index`, returning the range of its bytes and the range of its encoding.
+    pub(crate) fn span(&self, index: usize) -> Option<(Range<usize>, Range<usize>)> {
+        if index >= self.len {
+            return None;
+        }
+        let offset = match self.index {
+            Some(offsets) => *offsets.get(index)?,
+            None => {
+                let mut offset = self.format.header_len();
+                for _ in 0..index {
+                    offset = self.format.read_part(self.data, offset).ok()?.1;
+                }
+                offset
+            }
+        };
+        let (range, next) = self.format.read_part(self.data, offset).ok()?;
+        Some((range, offset..next))
+    }
+
+    /// Get the index of part offsets borrowed from a `Buffer`, if there is one.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn index(&self) -> Option<&'a [usize]> {
+        self.index
+    }
+
+    /// Collect the offset of every part.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn offsets(&self) -> Vec<usize> {
+        self.spans().map(|(_, span)| span.start).collect()
+    }
+
+    /// Iterate over the range of the bytes and the range of the encoding of every part.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn spans(&self) -> Spans<'a> {
+        Spans { data: self.data, format: self.format, offset: self.format.header_len() }
+    }
+
+    /// Get the last part.
+    pub fn last(&self) -> Option<&'a [u8]> {
+        self.iter().next_back()
+    }
+
+    /// Copy the viewed bytes into an owned `Buffer`.
+    #[cfg(feature = "alloc")]
+    pub fn to_buffer(&self) -> Buffer {
+        Buffer::from_raw_parts(self.data.to_vec(), self.format, self.len)
+    }
+}
+
+/// Iterator over the locations of parts, see `BufferRef::spans`
+#[cfg(feature = "alloc")]
+pub(crate) struct Spans<'a> {
+    data: &'a [u8],
+    format: Format,
+    offset: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Iterator for Spans<'a> {
+    type Item = (Range<usize>, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+        let (range, next) = self.format.read_part(self.data, self.offset).ok()?;
+        let span = self.offset..next;
+        self.offset = next;
+        Some((range, span))
+    }
+}
+
+/// Count the parts in `data`, failing on the first framing or checksum error.
+fn count_parts(data: &[u8], format: Format) -> Result<usize, SplitBufferError> {
+    let mut parts = BufferIterator::new(data, format, usize::MAX, None);
+    let mut len = 0;
+    while let Some(part) = parts.try_next(true) {
+        part?;
+        len += 1;
+    }
+    Ok(len)
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> From<&'a Buffer> for BufferRef<'a> {
+    fn from(buffer: &'a Buffer) -> Self {
+        buffer.as_buffer_ref()
+    }
+}
+
+impl<'a> core::convert::TryFrom<&'a [u8]> for BufferRef<'a> {
+    type Error = SplitBufferError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        BufferRef::from_slice(bytes)
+    }
+}
+
+impl<'a> IntoIterator for BufferRef<'a> {
+    type Item = &'a [u8];
+    type IntoIter = BufferIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &BufferRef<'a> {
+    type Item = &'a [u8];
+    type IntoIter = BufferIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use core::convert::TryFrom;
+
+    use super::*;
+    use crate::LengthEncoding;
+
+    #[test]
+    fn parts_borrow_from_slice() {
+        let parts: &[&[u8]] = &[b"abc", b"", b"de"];
+        let data = Buffer::build_with_format(parts, LengthEncoding::Varint).into_inner();
+        let view = BufferRef::from_slice_with_format(&data, LengthEncoding::Varint).unwrap();
+        assert_eq!(view.as_bytes().as_ptr(), data.as_ptr());
+        for part in view {
+            let offset = part.as_ptr() as usize - data.as_ptr() as usize;
+            assert_eq!(&data[offset..offset + part.len()], part);
+        }
+        assert!(view.iter().eq(parts.iter().copied()));
+
+        let buffer = view.to_buffer();
+        assert_eq!(buffer.format(), view.format());
+        assert_eq!(buffer.as_bytes(), &data[..]);
+        assert_eq!(BufferRef::from(&buffer).as_bytes().as_ptr(), buffer.as_bytes().as_ptr());
+    }
+
+    #[test]
+    fn from_slice_validates() {
+        let data = Buffer::build([b"abc"]).into_inner();
+        assert!(BufferRef::try_from(&data[..]).unwrap().iter().eq([&b"abc"[..]]));
+        assert_eq!(
+            BufferRef::from_slice(&data[..10]).unwrap_err(),
+            SplitBufferError::PartOutOfBounds { offset: 0, len: 3, available: 2 }
+        );
+        assert_eq!(
+            BufferRef::from_slice_with_format(&data, LengthEncoding::U32).unwrap_err(),
+            SplitBufferError::PartOutOfBounds { offset: 7, len: 0x6362_6100, available: 0 }
+        );
+    }
+}
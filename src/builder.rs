@@ -0,0 +1,232 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::{Buffer, Format};
+
+/// Incremental builder for a `Buffer`
+///
+/// Parts are encoded as they are pushed, so they never need to be collected upfront.
+#[derive(Clone, Debug, Default)]
+pub struct BufferBuilder {
+    data: Vec<u8>,
+    format: Format,
+    len: usize,
+}
+
+impl BufferBuilder {
+    /// Create an empty builder using the default `Format`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty builder with room for `capacity` encoded bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_format_and_capacity(Format::default(), capacity)
+    }
+
+    /// Create an empty builder using the given `Format`.
+    pub fn with_format<F: Into<Format>>(format: F) -> Self {
+        Self::with_format_and_capacity(format, 0)
+    }
+
+    /// Create an empty builder using the given `Format` with room for `capacity` encoded bytes.
+    pub fn with_format_and_capacity<F: Into<Format>>(format: F, capacity: usize) -> Self {
+        let format = format.into();
+        let mut data = Vec::with_capacity(capacity);
+        format.write_header(&mut data);
+        BufferBuilder { data, format, len: 0 }
+    }
+
+    /// Reserve room for at least `additional` more encoded bytes.
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+    }
+
+    /// Get the number of parts pushed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether no parts have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append a part.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format uses `LengthEncoding::U32` and the part is longer than `u32::MAX`
+    /// bytes.
+    pub fn push<T: AsRef<[u8]>>(&mut self, part: T) -> &mut Self {
+        self.format.write_part(&mut self.data, part.as_ref());
+        self.len += 1;
+        self
+    }
+
+    /// Append a part by writing its bytes in place.
+    ///
+    /// The length of the part is filled in once `f` returns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format uses `LengthEncoding::U32` and the part is longer than `u32::MAX`
+    /// bytes.
+    pub fn push_with<F: FnOnce(&mut PartWriter) -> R, R>(&mut self, f: F) -> R {
+        let lengths = self.format.lengths();
+        let offset = self.data.len();
+        let placeholder = lengths.encoded_len(0);
+        self.data.resize(offset + placeholder, 0);
+
+        let start = self.data.len();
+        let result = f(&mut PartWriter { buffer: &mut self.data, start });
+        let len = self.data.len() - start;
+
+        let prefix = lengths.encode(len);
+        let prefix = prefix.as_ref();
+        if prefix.len() > placeholder {
+            self.data.splice(start..start, prefix[placeholder..].iter().copied());
+        }
+        self.data[offset..offset + prefix.len()].copy_from_slice(prefix);
+        let suffix = self.format.encode_suffix(&self.data[self.data.len() - len..]);
+        self.data.extend_from_slice(suffix.as_ref());
+
+        self.len += 1;
+        result
+    }
+
+    /// Finish building, returning the `Buffer`.
+    pub fn finish(self) -> Buffer {
+        Buffer::from_raw_parts(self.data, self.format, self.len)
+    }
+}
+
+impl<T: AsRef<[u8]>> Extend<T> for BufferBuilder {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, parts: I) {
+        for part in parts {
+            self.push(part);
+        }
+    }
+}
+
+/// Writer for a single part, passed to `BufferBuilder::push_with`
+///
+/// Bytes can only be appended to the part being written.
+pub struct PartWriter<'a> {
+    buffer: &'a mut Vec<u8>,
+    start: usize,
+}
+
+impl<'a> PartWriter<'a> {
+    /// Append bytes to the part.
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Append a single byte to the part.
+    pub fn push(&mut self, byte: u8) {
+        self.buffer.push(byte);
+    }
+
+    /// Get the number of bytes written to the part so far.
+    pub fn len(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    /// Check whether no bytes have been written to the part.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the bytes written to the part so far.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.buffer[self.start..]
+    }
+}
+
+impl<'a> Extend<u8> for PartWriter<'a> {
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, bytes: I) {
+        self.buffer.extend(bytes);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> io::Write for PartWriter<'a> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::LengthEncoding;
+
+    fn formats() -> Vec<Format> {
+        let mut formats = Vec::new();
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            formats.push(Format::new(lengths));
+            formats.push(Format::new(lengths).with_trailing_lengths(true));
+            formats.push(Format::new(lengths).with_checksums(true));
+            formats.push(Format::new(lengths).with_trailing_lengths(true).with_checksums(true));
+        }
+        formats
+    }
+
+    #[test]
+    fn push_matches_build() {
+        let parts: &[&[u8]] = &[b"abc", b"", b"de"];
+        for format in formats() {
+            let mut builder = BufferBuilder::with_format_and_capacity(format, 64);
+            assert!(builder.is_empty());
+            builder.push(parts[0]).push(parts[1]);
+            builder.extend(&parts[2..]);
+            assert_eq!(builder.len(), 3);
+            let buffer = builder.finish();
+            assert_eq!(buffer.len(), 3);
+            assert_eq!(buffer.as_bytes(), Buffer::build_with_format(parts, format).as_bytes());
+            assert_eq!(buffer.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn push_with_grows_prefix() {
+        let parts = vec![vec![1; 0], vec![2; 127], vec![3; 128], vec![4; 20_000]];
+        for format in formats() {
+            let mut builder = BufferBuilder::with_format(format);
+            for part in &parts {
+                builder.push_with(|writer| writer.extend(part.iter().copied()));
+            }
+            let buffer = builder.finish();
+            assert_eq!(buffer.as_bytes(), Buffer::build_with_format(&parts, format).as_bytes());
+            assert_eq!(buffer.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn part_writer() {
+        let mut builder = BufferBuilder::new();
+        builder.push(b"first");
+        let len = builder.push_with(|writer| {
+            assert!(writer.is_empty());
+            writer.push(b'a');
+            writer.extend_from_slice(b"bc");
+            #[cfg(feature = "std")]
+            io::Write::write_all(writer, b"de").unwrap();
+            #[cfg(not(feature = "std"))]
+            writer.extend_from_slice(b"de");
+            writer.as_mut_slice()[0] = b'A';
+            writer.len()
+        });
+        assert_eq!(len, 5);
+        assert!(builder.finish().iter().eq([&b"first"[..], b"Abcde"]));
+    }
+}
@@ -0,0 +1,441 @@
+use std::io;
+use std::vec::Vec;
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::decoder::PartDecoder;
+use crate::writer::check_part_len;
+use crate::{Buffer, Format, LengthEncoding, SplitBufferError};
+
+/// `tokio_util` codec for parts encoded in the same framing as `Buffer`
+///
+/// A stream of encoded parts is a `Buffer` sent in pieces: the bytes of `Buffer::into_inner`
+/// decode into its parts, and encoding parts produces bytes that `Buffer::from_vec_with_format`
+/// accepts. The header, if the format has one, is written before the first item encoded and
+/// expected before the first part decoded.
+///
+/// Decoding fails with an `io::Error` of kind `InvalidData` wrapping a `SplitBufferError` if the
+/// framing is malformed or a checksum does not match.
+///
+/// Only individual parts are decoded. The stream carries no part count or end marker, so encoding
+/// a `Buffer` sends the same bytes as encoding its parts one by one. Use `BufferCodec` to send
+/// whole buffers.
+#[derive(Clone, Debug, Default)]
+pub struct SplitBufferCodec {
+    format: Format,
+    decoder: PartDecoder,
+    header_written: bool,
+}
+
+impl SplitBufferCodec {
+    /// Create a codec using the default `Format`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a codec using the given `Format`.
+    pub fn with_format<F: Into<Format>>(format: F) -> Self {
+        let format = format.into();
+        SplitBufferCodec { format, decoder: PartDecoder::new(format), header_written: false }
+    }
+
+    /// Reject decoded parts longer than `max` bytes with `SplitBufferError::PartTooLong`.
+    pub fn with_max_part_len(mut self, max: usize) -> Self {
+        self.decoder.set_max_part_len(max);
+        self
+    }
+
+    /// Get the `Format` parts are encoded in
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    fn encode_header(&mut self, dst: &mut BytesMut) {
+        if self.header_written {
+            return;
+        }
+        self.header_written = true;
+        put_header(self.format, dst);
+    }
+
+    fn encode_part(&mut self, part: &[u8], dst: &mut BytesMut) -> io::Result<()> {
+        check_part_len(self.format, part.len())?;
+        self.encode_header(dst);
+        put_part(self.format, part, dst);
+        Ok(())
+    }
+}
+
+impl Decoder for SplitBufferCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        match self.decoder.read_header(src, false)? {
+            Some(header) => src.advance(header),
+            None => return Ok(None),
+        }
+        let (range, next) = match self.decoder.read_part(src)? {
+            Some(part) => part,
+            None => return Ok(None),
+        };
+        let mut part = src.split_to(next);
+        part.advance(range.start);
+        part.truncate(range.len());
+        Ok(Some(part))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        match self.decode(src)? {
+            Some(part) => Ok(Some(part)),
+            None if src.is_empty() => Ok(None),
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+impl<'a> Encoder<&'a [u8]> for SplitBufferCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, part: &'a [u8], dst: &mut BytesMut) -> io::Result<()> {
+        self.encode_part(part, dst)
+    }
+}
+
+impl Encoder<Bytes> for SplitBufferCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, part: Bytes, dst: &mut BytesMut) -> io::Result<()> {
+        self.encode_part(&part, dst)
+    }
+}
+
+/// Encodes every part of the buffer, copying the encoded bytes directly if the buffer has the
+/// same framing as the codec.
+impl<'a> Encoder<&'a Buffer> for SplitBufferCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, buffer: &'a Buffer, dst: &mut BytesMut) -> io::Result<()> {
+        if !buffer.format().same_framing(&self.format) {
+            return buffer.iter().try_for_each(|part| self.encode_part(part, dst));
+        }
+        self.encode_header(dst);
+        dst.extend_from_slice(&buffer.as_bytes()[buffer.format().header_len()..]);
+        Ok(())
+    }
+}
+
+/// `tokio_util` codec for whole `Buffer`s
+///
+/// Each frame is the length of an encoded buffer, in the `LengthEncoding` of the codec's
+/// `Format`, followed by the bytes of `Buffer::into_inner` in that `Format`, header included if
+/// it has one. Buffers in another `Format` are re-encoded part by part.
+///
+/// Decoded frames are checked with `Buffer::from_vec_with_format`. Decoding fails with an
+/// `io::Error` of kind `InvalidData` wrapping a `SplitBufferError` if the framing is malformed or a
+/// checksum does not match; offsets in errors count from the start of the stream.
+#[derive(Clone, Debug, Default)]
+pub struct BufferCodec {
+    format: Format,
+    max_len: Option<usize>,
+    offset: usize,
+}
+
+impl BufferCodec {
+    /// Create a codec using the default `Format`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a codec using the given `Format`.
+    pub fn with_format<F: Into<Format>>(format: F) -> Self {
+        BufferCodec { format: format.into(), ..Self::default() }
+    }
+
+    /// Reject frames longer than `max` encoded bytes with `SplitBufferError::PartTooLong`, before
+    /// waiting for the rest of the frame.
+    pub fn with_max_len(mut self, max: usize) -> Self {
+        self.max_len = Some(max);
+        self
+    }
+
+    /// Get the `Format` buffers are encoded in
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    fn encode_len(&self, len: usize, dst: &mut BytesMut) -> io::Result<()> {
+        if self.format.lengths() == LengthEncoding::U32 && len > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "encoded buffer length must fit in `u32`",
+            ));
+        }
+        let prefix = self.format.lengths().encode(len);
+        dst.reserve(prefix.as_ref().len() + len);
+        dst.extend_from_slice(prefix.as_ref());
+        Ok(())
+    }
+}
+
+impl Decoder for BufferCodec {
+    type Item = Buffer;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Buffer>> {
+        if src.is_empty() {
+            return Ok(None);
+        }
+        let offset = self.offset;
+        let (len, read) = match self.format.lengths().read(src, 0) {
+            Ok(prefix) => prefix,
+            Err(SplitBufferError::TruncatedLength { .. }) => return Ok(None),
+            Err(e) => return Err(e.shifted(offset).into()),
+        };
+        if let Some(max) = self.max_len.filter(|max| len > *max) {
+            return Err(SplitBufferError::PartTooLong { offset, len, max }.into());
+        }
+        if src.len() - read < len {
+            return Ok(None);
+        }
+
+        src.advance(read);
+        let frame = src.split_to(len);
+        let buffer = Buffer::from_vec_with_format(frame.to_vec(), self.format)
+            .map_err(|e| e.shifted(offset + read))?;
+        self.offset += read + len;
+        Ok(Some(buffer))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> io::Result<Option<Buffer>> {
+        match self.decode(src)? {
+            Some(buffer) => Ok(Some(buffer)),
+            None if src.is_empty() => Ok(None),
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+/// Encodes the buffer as one frame, copying the encoded bytes directly if the buffer has the same
+/// framing as the codec.
+impl<'a> Encoder<&'a Buffer> for BufferCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, buffer: &'a Buffer, dst: &mut BytesMut) -> io::Result<()> {
+        let format = self.format;
+        if buffer.format().same_framing(&format) {
+            let parts = &buffer.as_bytes()[buffer.format().header_len()..];
+            self.encode_len(format.header_len() + parts.len(), dst)?;
+            put_header(format, dst);
+            dst.extend_from_slice(parts);
+            return Ok(());
+        }
+
+        let mut len = format.header_len();
+        for part in buffer {
+            check_part_len(format, part.len())?;
+            len += format.encoded_len(part.len());
+        }
+        self.encode_len(len, dst)?;
+        put_header(format, dst);
+        for part in buffer {
+            put_part(format, part, dst);
+        }
+        Ok(())
+    }
+}
+
+fn put_header(format: Format, dst: &mut BytesMut) {
+    let mut header = Vec::new();
+    format.write_header(&mut header);
+    dst.extend_from_slice(&header);
+}
+
+fn put_part(format: Format, part: &[u8], dst: &mut BytesMut) {
+    dst.reserve(format.encoded_len(part.len()));
+    dst.extend_from_slice(format.lengths().encode(part.len()).as_ref());
+    dst.extend_from_slice(part);
+    dst.extend_from_slice(format.encode_suffix(part).as_ref());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{formats, parts, split_buffer_error};
+
+    fn encode(codec: &mut SplitBufferCodec, parts: &[Vec<u8>]) -> BytesMut {
+        let mut encoded = BytesMut::new();
+        for part in parts {
+            codec.encode(&part[..], &mut encoded).unwrap();
+        }
+        encoded
+    }
+
+    /// Feed `bytes` to the decoder one byte at a time, collecting every decoded item.
+    fn decode_bytewise<D: Decoder<Error = io::Error>>(
+        codec: &mut D,
+        bytes: &[u8],
+    ) -> io::Result<Vec<D::Item>> {
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        for &byte in bytes {
+            src.extend_from_slice(&[byte]);
+            while let Some(item) = codec.decode(&mut src)? {
+                decoded.push(item);
+            }
+        }
+        while let Some(item) = codec.decode_eof(&mut src)? {
+            decoded.push(item);
+        }
+        Ok(decoded)
+    }
+
+    fn decode_parts(codec: &mut SplitBufferCodec, bytes: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+        Ok(decode_bytewise(codec, bytes)?.iter().map(|part| part.to_vec()).collect())
+    }
+
+    #[test]
+    fn decode_byte_at_a_time() {
+        for format in formats() {
+            let encoded = encode(&mut SplitBufferCodec::with_format(format), &parts());
+            assert_eq!(&encoded[..], Buffer::build_with_format(parts(), format).as_bytes());
+            let decoded = decode_parts(&mut SplitBufferCodec::with_format(format), &encoded);
+            assert_eq!(decoded.unwrap(), parts(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn decode_eof_truncated() {
+        for format in formats() {
+            let encoded = encode(&mut SplitBufferCodec::with_format(format), &parts());
+            for &len in &[1, format.header_len() + 1, encoded.len() - 1] {
+                let err = decode_parts(&mut SplitBufferCodec::with_format(format), &encoded[..len])
+                    .unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_buffer() {
+        for format in formats() {
+            for buffer_format in formats() {
+                let buffer = Buffer::build_with_format(parts(), buffer_format);
+                let mut codec = SplitBufferCodec::with_format(format);
+                let mut encoded = BytesMut::new();
+                codec.encode(&buffer, &mut encoded).unwrap();
+                codec.encode(&buffer, &mut encoded).unwrap();
+
+                let mut expected = parts();
+                expected.extend(parts());
+                let decoded = decode_parts(&mut SplitBufferCodec::with_format(format), &encoded);
+                assert_eq!(decoded.unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let format = Format::new(LengthEncoding::U32).with_checksums(true).with_header(true);
+        let mut encoded = encode(&mut SplitBufferCodec::with_format(format), &parts());
+
+        let err = decode_parts(&mut SplitBufferCodec::with_format(format), &encoded[8..]);
+        assert_eq!(split_buffer_error(&err.unwrap_err()), &SplitBufferError::InvalidHeader);
+
+        let mut codec = SplitBufferCodec::with_format(format).with_max_part_len(127);
+        let err = decode_parts(&mut codec, &encoded).unwrap_err();
+        assert_eq!(
+            split_buffer_error(&err),
+            &SplitBufferError::PartTooLong { offset: 8 + 11 + 8 + 135, len: 128, max: 127 }
+        );
+
+        encoded[8 + 11 + 8 + 4] ^= 1;
+        let err = decode_parts(&mut SplitBufferCodec::with_format(format), &encoded).unwrap_err();
+        assert_eq!(
+            split_buffer_error(&err),
+            &SplitBufferError::ChecksumMismatch { index: 2, offset: 8 + 11 + 8 }
+        );
+    }
+
+    #[test]
+    fn buffer_round_trip() {
+        for format in formats() {
+            for buffer_format in formats() {
+                let buffers = [
+                    Buffer::build_with_format(parts(), buffer_format),
+                    Buffer::build_with_format(Vec::<Vec<u8>>::new(), buffer_format),
+                    Buffer::build_with_format([b"x"], buffer_format),
+                ];
+                let mut codec = BufferCodec::with_format(format);
+                let mut encoded = BytesMut::new();
+                for buffer in &buffers {
+                    codec.encode(buffer, &mut encoded).unwrap();
+                }
+
+                let decoded = decode_bytewise(&mut BufferCodec::with_format(format), &encoded);
+                let decoded = decoded.unwrap();
+                assert_eq!(decoded.len(), buffers.len());
+                for (decoded, buffer) in decoded.iter().zip(&buffers) {
+                    assert_eq!(decoded.format(), format);
+                    assert_eq!(decoded.is_indexed(), format.is_indexed());
+                    assert!(decoded.iter().eq(buffer.iter()), "{:?} {:?}", format, buffer_format);
+                    let expected =
+                        Buffer::build_with_format(buffer.iter().collect::<Vec<_>>(), format);
+                    assert_eq!(decoded.as_bytes(), expected.as_bytes());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_frame_layout() {
+        let buffer = Buffer::build([b"ab"]);
+        let mut encoded = BytesMut::new();
+        BufferCodec::new().encode(&buffer, &mut encoded).unwrap();
+        assert_eq!(&encoded[..], &[10, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, b'a', b'b']);
+
+        let format = Format::new(LengthEncoding::Varint).with_header(true);
+        let mut encoded = BytesMut::new();
+        BufferCodec::with_format(format).encode(&buffer, &mut encoded).unwrap();
+        assert_eq!(encoded.len(), 1 + 8 + 3);
+        assert_eq!(encoded[0], 11);
+        assert_eq!(&encoded[1..5], b"SBUF");
+    }
+
+    #[test]
+    fn buffer_decode_errors() {
+        let format = Format::new(LengthEncoding::U32).with_checksums(true);
+        let mut encoded = BytesMut::new();
+        let mut codec = BufferCodec::with_format(format);
+        codec.encode(&Buffer::build_with_format([b"ab"], format), &mut encoded).unwrap();
+        codec.encode(&Buffer::build_with_format(parts(), format), &mut encoded).unwrap();
+        let first = 4 + 4 + 2 + 4;
+
+        let mut codec = BufferCodec::with_format(format).with_max_len(first - 4);
+        let decoded = decode_bytewise(&mut codec, &encoded[..first]).unwrap();
+        assert_eq!(decoded[0].get(0), Some(&b"ab"[..]));
+        let err = decode_bytewise(&mut codec, &encoded[first..]).unwrap_err();
+        assert_eq!(
+            split_buffer_error(&err),
+            &SplitBufferError::PartTooLong {
+                offset: first,
+                len: encoded.len() - first - 4,
+                max: 10
+            }
+        );
+
+        for &len in &[2, 5, first + 3, encoded.len() - 1] {
+            let err = decode_bytewise(&mut BufferCodec::with_format(format), &encoded[..len]);
+            assert_eq!(err.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        // Corrupt the checksum of the second part of the second frame.
+        encoded[first + 4 + 11 + 4] ^= 1;
+        let err = decode_bytewise(&mut BufferCodec::with_format(format), &encoded).unwrap_err();
+        assert_eq!(
+            split_buffer_error(&err),
+            &SplitBufferError::ChecksumMismatch { index: 1, offset: first + 4 + 11 }
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::ops::Range;
+
+use crate::format::HEADER_LEN;
+use crate::{Format, SplitBufferError};
+
+/// Decodes parts from the front of bytes that arrive from a stream in pieces
+///
+/// Offsets in errors count from the start of the stream.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PartDecoder {
+    format: Format,
+    detect: bool,
+    header_error: Option<SplitBufferError>,
+    header_read: bool,
+    max_part_len: Option<usize>,
+    offset: usize,
+    position: usize,
+}
+
+impl PartDecoder {
+    /// Decode a stream in the given `Format`, checking its header if it should have one.
+    pub(crate) fn new(format: Format) -> Self {
+        PartDecoder { format, ..Self::default() }
+    }
+
+    /// Decode a stream in the `Format` recorded in its header, or the default `Format` if there
+    /// is no header.
+    ///
+    /// A stream that starts with the magic bytes but no readable header is decoded in the default
+    /// `Format`, and the header error is reported if its first part cannot be decoded.
+    #[cfg(feature = "futures")]
+    pub(crate) fn detect() -> Self {
+        PartDecoder { detect: true, ..Self::default() }
+    }
+
+    #[cfg(feature = "futures")]
+    pub(crate) fn format(&self) -> Format {
+        self.format
+    }
+
+    pub(crate) fn set_max_part_len(&mut self, max: usize) {
+        self.max_part_len = Some(max);
+    }
+
+    /// Read the header at the start of `bytes` if it has not been read yet, returning the number
+    /// of bytes it used, or `None` if more bytes are needed.
+    ///
+    /// `eof` signals that no more bytes will arrive.
+    pub(crate) fn read_header(
+        &mut self,
+        bytes: &[u8],
+        eof: bool,
+    ) -> Result<Option<usize>, SplitBufferError> {
+        if self.header_read {
+            return Ok(Some(0));
+        }
+        // A short stream is headerless when detecting, but cut off within the header otherwise.
+        if bytes.len() < HEADER_LEN && (self.format.has_header() || (self.detect && !eof)) {
+            return Ok(None);
+        }
+        if self.detect {
+            match Format::detect(bytes) {
+                Ok(format) => self.format = format,
+                Err(e) => self.header_error = Some(e),
+            }
+        } else {
+            self.format.check_header(bytes)?;
+        }
+        self.header_read = true;
+        self.offset += self.format.header_len();
+        Ok(Some(self.format.header_len()))
+    }
+
+    /// Take the error from reading a header that was skipped when detecting the format, if the
+    /// first part has not been decoded yet.
+    #[cfg(feature = "futures")]
+    pub(crate) fn take_header_error(&mut self) -> Option<SplitBufferError> {
+        self.header_error.take()
+    }
+
+    /// Read the part at the start of `bytes`, returning the range of its bytes and the length of
+    /// its encoding, or `None` if more bytes are needed.
+    ///
+    /// The header must have been read first.
+    pub(crate) fn read_part(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<Option<(Range<usize>, usize)>, SplitBufferError> {
+        match self.read_next(bytes) {
+            Ok(Some(part)) => {
+                self.header_error = None;
+                Ok(Some(part))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(self.header_error.take().unwrap_or(e)),
+        }
+    }
+
+    fn read_next(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<Option<(Range<usize>, usize)>, SplitBufferError> {
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        let offset = self.offset;
+        let len = match self.format.lengths().read(bytes, 0) {
+            Ok((len, _)) => len,
+            Err(SplitBufferError::TruncatedLength { .. }) => return Ok(None),
+            Err(e) => return Err(e.shifted(offset)),
+        };
+        if let Some(max) = self.max_part_len.filter(|max| len > *max) {
+            return Err(SplitBufferError::PartTooLong { offset, len, max });
+        }
+
+        // Wait for the whole part, so that errors below are never caused by bytes yet to arrive.
+        if bytes.len() < self.format.encoded_len(len) {
+            return Ok(None);
+        }
+
+        let (range, next) = self.format.read_part(bytes, 0).map_err(|e| e.shifted(offset))?;
+        if !self.format.verify_checksum(bytes, range.clone()) {
+            let index = self.position;
+            return Err(SplitBufferError::ChecksumMismatch { index, offset });
+        }
+
+        self.offset += next;
+        self.position += 1;
+        Ok(Some((range, next)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::LengthEncoding;
+
+    fn varint_trailing() -> Format {
+        Format::new(LengthEncoding::Varint).with_trailing_lengths(true)
+    }
+
+    #[test]
+    fn read_part_waits_for_whole_part() {
+        let bytes = [2, b'a', b'b', 2, 1, b'c', 1];
+        let mut decoder = PartDecoder::new(varint_trailing());
+        assert_eq!(decoder.read_header(&[], false), Ok(Some(0)));
+        for len in 0..4 {
+            assert_eq!(decoder.read_part(&bytes[..len]), Ok(None));
+        }
+        assert_eq!(decoder.read_part(&bytes), Ok(Some((1..3, 4))));
+        assert_eq!(decoder.read_part(&bytes[4..6]), Ok(None));
+        assert_eq!(decoder.read_part(&bytes[4..]), Ok(Some((1..2, 3))));
+    }
+
+    #[test]
+    fn read_part_corrupted_trailer() {
+        // The trailer is complete, so it must be rejected rather than waited on.
+        let mut decoder = PartDecoder::new(varint_trailing());
+        decoder.read_header(&[], false).unwrap();
+        assert_eq!(decoder.read_part(&[1, b'c', 1]), Ok(Some((1..2, 3))));
+        assert_eq!(
+            decoder.read_part(&[2, b'a', b'b', 0x82]),
+            Err(SplitBufferError::TruncatedLength { offset: 6 })
+        );
+    }
+
+    #[test]
+    fn read_part_huge_length() {
+        let mut decoder = PartDecoder::new(LengthEncoding::U64.into());
+        decoder.read_header(&[], false).unwrap();
+        assert_eq!(decoder.read_part(&[0xff; 9]), Ok(None));
+    }
+
+    #[test]
+    fn read_header_waits_for_whole_header() {
+        let format = Format::new(LengthEncoding::U32).with_header(true);
+        let mut header = Vec::new();
+        format.write_header(&mut header);
+        let mut decoder = PartDecoder::new(format);
+        assert_eq!(decoder.read_header(&header[..7], false), Ok(None));
+        assert_eq!(decoder.read_header(&header, false), Ok(Some(8)));
+        assert_eq!(decoder.read_header(&[], false), Ok(Some(0)));
+
+        let mut decoder = PartDecoder::new(format.with_checksums(true));
+        assert_eq!(
+            decoder.read_header(&header, false),
+            Err(SplitBufferError::FormatMismatch {
+                expected: format.with_checksums(true),
+                found: format
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "futures")]
+    fn detect_short_stream() {
+        let mut header = Vec::new();
+        Format::default().with_header(true).write_header(&mut header);
+        let mut decoder = PartDecoder::detect();
+        assert_eq!(decoder.read_header(&header[..7], false), Ok(None));
+        assert_eq!(decoder.read_header(&header[..7], true), Ok(Some(0)));
+        assert_eq!(decoder.format(), Format::default());
+        assert_eq!(decoder.read_part(&header[..7]), Ok(None));
+        assert_eq!(decoder.take_header_error(), Some(SplitBufferError::InvalidHeader));
+    }
+
+    #[test]
+    #[cfg(feature = "futures")]
+    fn detect_unsupported_header() {
+        let mut bytes = Vec::new();
+        Format::default().with_header(true).write_header(&mut bytes);
+        bytes[4] = 2;
+        bytes.extend_from_slice(&[0; 8]);
+
+        // Read as a headerless part of 0x0000_0002_4655_4253 bytes.
+        let mut decoder = PartDecoder::detect();
+        decoder.set_max_part_len(1 << 20);
+        assert_eq!(decoder.read_header(&bytes, false), Ok(Some(0)));
+        assert_eq!(
+            decoder.read_part(&bytes),
+            Err(SplitBufferError::UnsupportedVersion { version: 2 })
+        );
+
+        // The error is kept for a stream that ends within the first part.
+        let mut bytes = Vec::new();
+        Format::default().with_header(true).write_header(&mut bytes);
+        bytes[4] = 2;
+        let mut decoder = PartDecoder::detect();
+        decoder.read_header(&bytes, true).unwrap();
+        assert_eq!(decoder.read_part(&bytes), Ok(None));
+        assert!(decoder.take_header_error().is_some());
+    }
+}
@@ -0,0 +1,134 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::Format;
+
+/// Errors encountered while decoding the parts of a buffer.
+///
+/// Offsets are byte offsets into the buffer data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SplitBufferError {
+    /// The length prefix starting at `offset` runs past the end of the data.
+    TruncatedLength { offset: usize },
+    /// The length prefix starting at `offset` is malformed or does not fit in a `usize`.
+    InvalidLength { offset: usize },
+    /// The part starting at `offset` claims `len` bytes, but only `available` bytes remain.
+    PartOutOfBounds { offset: usize, len: usize, available: usize },
+    /// The part starting at `offset` claims `len` bytes, more than the limit of `max` bytes.
+    PartTooLong { offset: usize, len: usize, max: usize },
+    /// The trailing length of the part starting at `offset` does not match its leading length.
+    LengthMismatch { offset: usize },
+    /// The checksum of part number `index`, starting at `offset`, does not match its bytes.
+    ChecksumMismatch { index: usize, offset: usize },
+    /// The header is truncated or malformed.
+    InvalidHeader,
+    /// The header has a format version this crate cannot read.
+    UnsupportedVersion { version: u8 },
+    /// The header sets flags for features this crate cannot read.
+    UnsupportedFlags { flags: u16 },
+    /// The header describes a different format than the one expected.
+    FormatMismatch { expected: Format, found: Format },
+}
+
+#[cfg(any(feature = "tokio", feature = "futures"))]
+impl SplitBufferError {
+    /// Move the offsets in the error `by` bytes further into the data.
+    pub(crate) fn shifted(self, by: usize) -> Self {
+        match self {
+            SplitBufferError::TruncatedLength { offset } => {
+                SplitBufferError::TruncatedLength { offset: offset + by }
+            }
+            SplitBufferError::InvalidLength { offset } => {
+                SplitBufferError::InvalidLength { offset: offset + by }
+            }
+            SplitBufferError::PartOutOfBounds { offset, len, available } => {
+                SplitBufferError::PartOutOfBounds { offset: offset + by, len, available }
+            }
+            SplitBufferError::PartTooLong { offset, len, max } => {
+                SplitBufferError::PartTooLong { offset: offset + by, len, max }
+            }
+            SplitBufferError::LengthMismatch { offset } => {
+                SplitBufferError::LengthMismatch { offset: offset + by }
+            }
+            SplitBufferError::ChecksumMismatch { index, offset } => {
+                SplitBufferError::ChecksumMismatch { index, offset: offset + by }
+            }
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for SplitBufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SplitBufferError::TruncatedLength { offset } => {
+                write!(f, "truncated length prefix at offset {}", offset)
+            }
+            SplitBufferError::InvalidLength { offset } => {
+                write!(f, "invalid length prefix at offset {}", offset)
+            }
+            SplitBufferError::PartOutOfBounds { offset, len, available } => write!(
+                f,
+                "part at offset {} has length {} but only {} bytes remain",
+                offset, len, available
+            ),
+            SplitBufferError::PartTooLong { offset, len, max } => write!(
+                f,
+                "part at offset {} has length {} which exceeds the limit of {} bytes",
+                offset, len, max
+            ),
+            SplitBufferError::LengthMismatch { offset } => {
+                write!(f, "mismatched trailing length for part at offset {}", offset)
+            }
+            SplitBufferError::ChecksumMismatch { index, offset } => {
+                write!(f, "checksum mismatch for part {} at offset {}", index, offset)
+            }
+            SplitBufferError::InvalidHeader => write!(f, "invalid header"),
+            SplitBufferError::UnsupportedVersion { version } => {
+                write!(f, "unsupported format version {}", version)
+            }
+            SplitBufferError::UnsupportedFlags { flags } => {
+                write!(f, "unsupported format flags {:#06x}", flags)
+            }
+            SplitBufferError::FormatMismatch { expected, found } => {
+                write!(f, "expected format {:?} but found {:?}", expected, found)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SplitBufferError {}
+
+/// Errors returned when a part cannot be added to a `SliceBuilder`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapacityError {
+    /// Encoding the part needs `required` bytes, but only `available` bytes are left in the slice.
+    InsufficientSpace { required: usize, available: usize },
+    /// The part is `len` bytes long, too long for the `LengthEncoding` of the format.
+    PartTooLong { len: usize },
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CapacityError::InsufficientSpace { required, available } => {
+                write!(f, "part needs {} bytes but only {} bytes remain", required, available)
+            }
+            CapacityError::PartTooLong { len } => {
+                write!(f, "part length {} does not fit in the length encoding", len)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CapacityError {}
+
+#[cfg(feature = "std")]
+impl From<SplitBufferError> for io::Error {
+    fn from(err: SplitBufferError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
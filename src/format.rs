@@ -0,0 +1,692 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::ops::Range;
+
+use crate::crc::crc32c;
+use crate::SplitBufferError;
+
+/// How the length of each part is written into a buffer.
+///
+/// All encodings are little-endian, so the same bytes decode identically on every platform.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LengthEncoding {
+    /// Four byte little-endian lengths. Parts must not be longer than `u32::MAX` bytes.
+    U32,
+    /// Eight byte little-endian lengths.
+    #[default]
+    U64,
+    /// Unsigned LEB128 lengths, using one byte for parts shorter than 128 bytes and one more
+    /// byte for every further 7 bits of length.
+    Varint,
+}
+
+impl LengthEncoding {
+    /// Number of bytes used by fixed width encodings.
+    pub(crate) fn width(self) -> Option<usize> {
+        match self {
+            LengthEncoding::U32 => Some(4),
+            LengthEncoding::U64 => Some(8),
+            LengthEncoding::Varint => None,
+        }
+    }
+
+    /// Number of bytes used to encode `len`.
+    pub(crate) fn encoded_len(self, len: usize) -> usize {
+        match self.width() {
+            Some(width) => width,
+            None => {
+                let bits = usize::BITS - len.leading_zeros();
+                core::cmp::max(1, (bits as usize + 6) / 7)
+            }
+        }
+    }
+
+    /// Encode `len` into a small stack buffer.
+    pub(crate) fn encode(self, len: usize) -> EncodedLength {
+        let mut encoded = EncodedLength { bytes: [0; 10], len: 0 };
+        match self {
+            LengthEncoding::U32 => {
+                let len: u32 = len.try_into().expect("Part length must fit in `u32`");
+                encoded.bytes[..4].copy_from_slice(&len.to_le_bytes());
+                encoded.len = 4;
+            }
+            LengthEncoding::U64 => {
+                encoded.bytes[..8].copy_from_slice(&(len as u64).to_le_bytes());
+                encoded.len = 8;
+            }
+            LengthEncoding::Varint => {
+                let mut len = len;
+                while len >= 0x80 {
+                    encoded.bytes[encoded.len] = (len as u8) | 0x80;
+                    encoded.len += 1;
+                    len >>= 7;
+                }
+                encoded.bytes[encoded.len] = len as u8;
+                encoded.len += 1;
+            }
+        }
+        encoded
+    }
+
+    /// Encode `len` so that it can be read backwards from its end with `read_trailer`.
+    pub(crate) fn encode_trailer(self, len: usize) -> EncodedLength {
+        let mut encoded = self.encode(len);
+        if self == LengthEncoding::Varint {
+            encoded.bytes[..encoded.len].reverse();
+        }
+        encoded
+    }
+
+    /// Read a length from the start of `bytes`, returning it with the number of bytes consumed.
+    ///
+    /// `offset` is only used to describe errors.
+    pub(crate) fn read(
+        self,
+        bytes: &[u8],
+        offset: usize,
+    ) -> Result<(usize, usize), SplitBufferError> {
+        let truncated = SplitBufferError::TruncatedLength { offset };
+        let invalid = SplitBufferError::InvalidLength { offset };
+        match self.width() {
+            Some(width) => {
+                let bytes = bytes.get(..width).ok_or(truncated)?;
+                Ok((decode_fixed(bytes).ok_or(invalid)?, width))
+            }
+            None => decode_varint(bytes.iter().copied(), truncated, invalid),
+        }
+    }
+
+    /// Read a length encoded by `encode_trailer` from the end of `bytes`, returning it with the
+    /// number of bytes consumed.
+    ///
+    /// `offset` is only used to describe errors.
+    pub(crate) fn read_trailer(
+        self,
+        bytes: &[u8],
+        offset: usize,
+    ) -> Result<(usize, usize), SplitBufferError> {
+        let truncated = SplitBufferError::TruncatedLength { offset };
+        let invalid = SplitBufferError::InvalidLength { offset };
+        match self.width() {
+            Some(width) => {
+                let start = bytes.len().checked_sub(width).ok_or(truncated)?;
+                Ok((decode_fixed(&bytes[start..]).ok_or(invalid)?, width))
+            }
+            None => decode_varint(bytes.iter().rev().copied(), truncated, invalid),
+        }
+    }
+}
+
+/// A length prefix or trailer encoded on the stack
+pub(crate) struct EncodedLength {
+    bytes: [u8; 10],
+    len: usize,
+}
+
+impl AsRef<[u8]> for EncodedLength {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// The checksum and trailing length of a part encoded on the stack
+pub(crate) struct EncodedSuffix {
+    bytes: [u8; 14],
+    len: usize,
+}
+
+impl AsRef<[u8]> for EncodedSuffix {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Decode a little-endian `u32` or `u64`, failing if it does not fit in a `usize`.
+fn decode_fixed(bytes: &[u8]) -> Option<usize> {
+    match bytes.len() {
+        4 => u32::from_le_bytes(bytes.try_into().ok()?).try_into().ok(),
+        _ => u64::from_le_bytes(bytes.try_into().ok()?).try_into().ok(),
+    }
+}
+
+/// Decode a canonical LEB128 value, returning it with the number of bytes consumed.
+fn decode_varint<I: Iterator<Item = u8>>(
+    bytes: I,
+    truncated: SplitBufferError,
+    invalid: SplitBufferError,
+) -> Result<(usize, usize), SplitBufferError> {
+    let mut len = 0u64;
+    for (i, byte) in bytes.take(10).enumerate() {
+        let bits = u64::from(byte & 0x7f);
+        if i == 9 && bits > 1 {
+            return Err(invalid);
+        }
+        len |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            // Reject padded encodings so every length has exactly one representation.
+            if i > 0 && byte == 0 {
+                return Err(invalid);
+            }
+            return Ok((len.try_into().map_err(|_| invalid)?, i + 1));
+        }
+        if i == 9 {
+            return Err(invalid);
+        }
+    }
+    Err(truncated)
+}
+
+const MAGIC: [u8; 4] = *b"SBUF";
+const VERSION: u8 = 1;
+pub(crate) const HEADER_LEN: usize = 8;
+
+const FLAG_TRAILING_LENGTHS: u16 = 1;
+const FLAG_INDEXED: u16 = 1 << 1;
+const FLAG_CHECKSUMS: u16 = 1 << 2;
+const KNOWN_FLAGS: u16 = FLAG_TRAILING_LENGTHS | FLAG_INDEXED | FLAG_CHECKSUMS;
+
+pub(crate) const CHECKSUM_LEN: usize = 4;
+
+/// Framing used to encode the parts of a `Buffer`.
+///
+/// The default format uses `LengthEncoding::U64`, which matches the bytes written by earlier
+/// versions of this crate on 64-bit little-endian hosts.
+///
+/// # Header
+///
+/// A format can optionally be recorded in an 8 byte header at the start of the buffer, so that
+/// `Buffer::from_vec` can select the right decoder without being told:
+///
+/// | Bytes  | Contents                                                          |
+/// |--------|-------------------------------------------------------------------|
+/// | `0..4` | Magic bytes `SBUF`                                                |
+/// | `4`    | Format version, currently `1`                                     |
+/// | `5`    | Length encoding: `0` for `U32`, `1` for `U64`, `2` for `Varint`   |
+/// | `6..8` | Little-endian flags: `1` for trailing lengths, `2` for an index,  |
+/// |        | `4` for checksums                                                 |
+///
+/// Remaining flag bits are reserved for compression. Buffers that set flags or versions this
+/// crate does not understand are rejected rather than misread, unless the bytes also decode as a
+/// headerless buffer in the default format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Format {
+    lengths: LengthEncoding,
+    trailing_lengths: bool,
+    checksums: bool,
+    indexed: bool,
+    header: bool,
+}
+
+impl Format {
+    /// Create a format using the given length encoding.
+    pub const fn new(lengths: LengthEncoding) -> Self {
+        Format { lengths, trailing_lengths: false, checksums: false, indexed: false, header: false }
+    }
+
+    /// Also write the length of each part after its bytes.
+    ///
+    /// This allows iterating a buffer from the back without an index, at the cost of a second
+    /// length per part.
+    pub const fn with_trailing_lengths(self, trailing_lengths: bool) -> Self {
+        Format { trailing_lengths, ..self }
+    }
+
+    /// Write a CRC-32C checksum after the bytes of each part.
+    ///
+    /// Checksums are verified by `Buffer::validate`, `Buffer::try_iter` and `Buffer::from_vec`.
+    /// Since every part is covered and a corrupted length misaligns the parts that follow it, this
+    /// also detects corruption anywhere in the buffer.
+    pub const fn with_checksums(self, checksums: bool) -> Self {
+        Format { checksums, ..self }
+    }
+
+    /// Build an offset index whenever a buffer in this format is built or loaded.
+    ///
+    /// See `Buffer::build_index`.
+    pub const fn with_index(self, indexed: bool) -> Self {
+        Format { indexed, ..self }
+    }
+
+    /// Write a header describing the format at the start of the buffer.
+    pub const fn with_header(self, header: bool) -> Self {
+        Format { header, ..self }
+    }
+
+    /// Get the length encoding.
+    pub const fn lengths(&self) -> LengthEncoding {
+        self.lengths
+    }
+
+    /// Check whether each part is followed by its length.
+    pub const fn has_trailing_lengths(&self) -> bool {
+        self.trailing_lengths
+    }
+
+    /// Check whether each part is followed by a checksum.
+    pub const fn has_checksums(&self) -> bool {
+        self.checksums
+    }
+
+    /// Check whether buffers in this format are indexed when built or loaded.
+    pub const fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    /// Check whether the format is recorded in a header.
+    pub const fn has_header(&self) -> bool {
+        self.header
+    }
+
+    /// Number of bytes taken by the header, if any.
+    pub(crate) fn header_len(&self) -> usize {
+        if self.header {
+            HEADER_LEN
+        } else {
+            0
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn write_header(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.encode_header()[..self.header_len()]);
+    }
+
+    /// Encode the header, of which only the first `header_len` bytes are used.
+    pub(crate) fn encode_header(&self) -> [u8; HEADER_LEN] {
+        let lengths = match self.lengths {
+            LengthEncoding::U32 => 0,
+            LengthEncoding::U64 => 1,
+            LengthEncoding::Varint => 2,
+        };
+        let mut flags = 0;
+        if self.trailing_lengths {
+            flags |= FLAG_TRAILING_LENGTHS;
+        }
+        if self.indexed {
+            flags |= FLAG_INDEXED;
+        }
+        if self.checksums {
+            flags |= FLAG_CHECKSUMS;
+        }
+        let mut header = [0; HEADER_LEN];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4] = VERSION;
+        header[5] = lengths;
+        header[6..].copy_from_slice(&flags.to_le_bytes());
+        header
+    }
+
+    /// Read the format from the header at the start of `bytes`.
+    pub(crate) fn read_header(bytes: &[u8]) -> Result<Self, SplitBufferError> {
+        let header = bytes.get(..HEADER_LEN).ok_or(SplitBufferError::InvalidHeader)?;
+        if header[..4] != MAGIC {
+            return Err(SplitBufferError::InvalidHeader);
+        }
+        if header[4] != VERSION {
+            return Err(SplitBufferError::UnsupportedVersion { version: header[4] });
+        }
+        let lengths = match header[5] {
+            0 => LengthEncoding::U32,
+            1 => LengthEncoding::U64,
+            2 => LengthEncoding::Varint,
+            _ => return Err(SplitBufferError::InvalidHeader),
+        };
+        let flags = u16::from_le_bytes([header[6], header[7]]);
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(SplitBufferError::UnsupportedFlags { flags });
+        }
+        Ok(Format::new(lengths)
+            .with_trailing_lengths(flags & FLAG_TRAILING_LENGTHS != 0)
+            .with_checksums(flags & FLAG_CHECKSUMS != 0)
+            .with_index(flags & FLAG_INDEXED != 0)
+            .with_header(true))
+    }
+
+    /// Read the format from the header of `bytes` if it starts with the magic bytes, or use the
+    /// default format.
+    ///
+    /// Headerless buffers in the default format only start with the magic bytes if their first
+    /// part is over 1 GiB long, so callers should fall back to the default format when the
+    /// header cannot be read.
+    pub(crate) fn detect(bytes: &[u8]) -> Result<Self, SplitBufferError> {
+        if bytes.starts_with(&MAGIC) {
+            Self::read_header(bytes)
+        } else {
+            Ok(Format::default())
+        }
+    }
+
+    /// Check whether parts are encoded identically in both formats, ignoring the header and
+    /// indexing.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn same_framing(&self, other: &Format) -> bool {
+        self.lengths == other.lengths
+            && self.trailing_lengths == other.trailing_lengths
+            && self.checksums == other.checksums
+    }
+
+    /// Check that `bytes` starts with a header matching this format, if it should have one.
+    pub(crate) fn check_header(&self, bytes: &[u8]) -> Result<(), SplitBufferError> {
+        if !self.header {
+            return Ok(());
+        }
+        match Self::read_header(bytes)? {
+            found if found == *self => Ok(()),
+            found => Err(SplitBufferError::FormatMismatch { expected: *self, found }),
+        }
+    }
+
+    /// Number of bytes needed to encode a part of `len` bytes.
+    pub(crate) fn encoded_len(&self, len: usize) -> usize {
+        // Saturate rather than overflow for lengths read from untrusted prefixes.
+        let trailer = if self.trailing_lengths { self.lengths.encoded_len(len) } else { 0 };
+        (self.lengths.encoded_len(len) + self.checksum_len() + trailer).saturating_add(len)
+    }
+
+    fn checksum_len(&self) -> usize {
+        if self.checksums {
+            CHECKSUM_LEN
+        } else {
+            0
+        }
+    }
+
+    /// Encode the bytes that follow a part: its checksum and trailing length, as enabled.
+    pub(crate) fn encode_suffix(&self, part: &[u8]) -> EncodedSuffix {
+        let mut suffix = EncodedSuffix { bytes: [0; 14], len: 0 };
+        if self.checksums {
+            suffix.bytes[..CHECKSUM_LEN].copy_from_slice(&crc32c(part).to_le_bytes());
+            suffix.len = CHECKSUM_LEN;
+        }
+        if self.trailing_lengths {
+            let trailer = self.lengths.encode_trailer(part.len());
+            let trailer = trailer.as_ref();
+            suffix.bytes[suffix.len..suffix.len + trailer.len()].copy_from_slice(trailer);
+            suffix.len += trailer.len();
+        }
+        suffix
+    }
+
+    /// Check the checksum following the part bytes in `data`, if the format has checksums.
+    pub(crate) fn verify_checksum(&self, bytes: &[u8], data: Range<usize>) -> bool {
+        if !self.checksums {
+            return true;
+        }
+        let checksum = &bytes[data.end..data.end + CHECKSUM_LEN];
+        crc32c(&bytes[data]).to_le_bytes() == checksum
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn write_part(&self, buffer: &mut Vec<u8>, part: &[u8]) {
+        buffer.extend_from_slice(self.lengths.encode(part.len()).as_ref());
+        buffer.extend_from_slice(part);
+        buffer.extend_from_slice(self.encode_suffix(part).as_ref());
+    }
+
+    /// Read the part starting at `offset`, returning the range of its bytes and the offset of the
+    /// next part.
+    pub(crate) fn read_part(
+        &self,
+        bytes: &[u8],
+        offset: usize,
+    ) -> Result<(Range<usize>, usize), SplitBufferError> {
+        let (len, read) = self.lengths.read(&bytes[offset..], offset)?;
+        let start = offset + read;
+        let available = bytes.len() - start;
+        if len.saturating_add(self.checksum_len()) > available {
+            return Err(SplitBufferError::PartOutOfBounds { offset, len, available });
+        }
+        let end = start + len;
+        let next = end + self.checksum_len();
+        if !self.trailing_lengths {
+            return Ok((start..end, next));
+        }
+
+        let trailer = bytes
+            .get(next..next + read)
+            .ok_or(SplitBufferError::TruncatedLength { offset: next })?;
+        match self.lengths.read_trailer(trailer, next)? {
+            (trailing, trailer_read) if trailing == len && trailer_read == read => {
+                Ok((start..end, next + read))
+            }
+            _ => Err(SplitBufferError::LengthMismatch { offset }),
+        }
+    }
+
+    /// Read the part ending at `end` using its trailing length, returning the range of its bytes
+    /// and the offset the part starts at.
+    pub(crate) fn read_part_back(
+        &self,
+        bytes: &[u8],
+        end: usize,
+    ) -> Result<(Range<usize>, usize), SplitBufferError> {
+        let (len, read) = self.lengths.read_trailer(&bytes[..end], end)?;
+        let offset = (end - read)
+            .checked_sub(len.saturating_add(self.checksum_len() + read))
+            .ok_or(SplitBufferError::LengthMismatch { offset: end })?;
+        match self.read_part(bytes, offset)? {
+            (range, next) if next == end => Ok((range, offset)),
+            _ => Err(SplitBufferError::LengthMismatch { offset }),
+        }
+    }
+}
+
+impl From<LengthEncoding> for Format {
+    fn from(lengths: LengthEncoding) -> Self {
+        Format::new(lengths)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn read_written_lengths() {
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64] {
+            for &len in &[0, 1, 0xff, 0x100, u32::MAX as usize] {
+                let bytes = lengths.encode(len);
+                let bytes = bytes.as_ref();
+                assert_eq!(bytes.len(), lengths.encoded_len(len));
+                assert_eq!(lengths.read(bytes, 0), Ok((len, bytes.len())));
+            }
+        }
+    }
+
+    #[test]
+    fn read_truncated_prefix() {
+        let truncated = Err(SplitBufferError::TruncatedLength { offset: 7 });
+        assert_eq!(LengthEncoding::U32.read(&[1, 0, 0], 7), truncated);
+        assert_eq!(LengthEncoding::U64.read(&[1, 0, 0, 0, 0, 0, 0], 7), truncated);
+        assert_eq!(LengthEncoding::Varint.read(&[], 7), truncated);
+        assert_eq!(LengthEncoding::Varint.read(&[0x80, 0x80], 7), truncated);
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for &len in &[0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u32::MAX as usize, usize::MAX] {
+            let bytes = LengthEncoding::Varint.encode(len);
+            let bytes = bytes.as_ref();
+            assert_eq!(bytes.len(), LengthEncoding::Varint.encoded_len(len));
+            assert_eq!(LengthEncoding::Varint.read(bytes, 0), Ok((len, bytes.len())));
+        }
+        assert_eq!(LengthEncoding::Varint.encode(300).as_ref(), [0xac, 0x02]);
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let invalid = Err(SplitBufferError::InvalidLength { offset: 0 });
+        let mut max = [0xff; 10];
+        max[9] = 0x01;
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(LengthEncoding::Varint.read(&max, 0), Ok((usize::MAX, 10)));
+
+        // The tenth byte may only hold the top bit of a `u64`.
+        max[9] = 0x02;
+        assert_eq!(LengthEncoding::Varint.read(&max, 0), invalid);
+        // An eleventh byte is never allowed.
+        assert_eq!(LengthEncoding::Varint.read(&[0xff; 11], 0), invalid);
+    }
+
+    #[test]
+    #[should_panic(expected = "Part length must fit in `u32`")]
+    fn encode_u32_overflow() {
+        LengthEncoding::U32.encode(u32::MAX as usize + 1);
+    }
+
+    #[test]
+    fn read_part_oversized_prefix() {
+        let format = Format::default();
+        let bytes = [0, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, b'a', b'b', b'c'];
+        assert_eq!(format.read_part(&bytes, 0), Ok((8..8, 8)));
+        assert_eq!(
+            format.read_part(&bytes, 8),
+            Err(SplitBufferError::PartOutOfBounds { offset: 8, len: 4, available: 3 })
+        );
+        assert_eq!(
+            format.read_part(&bytes[..12], 8),
+            Err(SplitBufferError::TruncatedLength { offset: 8 })
+        );
+    }
+
+    #[test]
+    fn varint_non_canonical() {
+        let invalid = Err(SplitBufferError::InvalidLength { offset: 3 });
+        assert_eq!(LengthEncoding::Varint.read(&[0x80, 0x00], 3), invalid);
+        assert_eq!(LengthEncoding::Varint.read(&[0x81, 0x80, 0x00], 3), invalid);
+        assert_eq!(LengthEncoding::Varint.read_trailer(&[0x00, 0x80], 3), invalid);
+        assert_eq!(LengthEncoding::Varint.read(&[0x00, 0x00], 3), Ok((0, 1)));
+    }
+
+    #[test]
+    fn trailer_round_trip() {
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            for &len in &[0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u32::MAX as usize] {
+                let trailer = lengths.encode_trailer(len);
+                let mut bytes = vec![0xaa];
+                bytes.extend_from_slice(trailer.as_ref());
+                assert_eq!(lengths.read_trailer(&bytes, 0), Ok((len, trailer.as_ref().len())));
+            }
+        }
+    }
+
+    #[test]
+    fn trailer_mismatch() {
+        let format = Format::new(LengthEncoding::U32).with_trailing_lengths(true);
+        let bytes = [3, 0, 0, 0, b'a', b'b', b'c', 3, 0, 0, 0];
+        assert_eq!(format.read_part(&bytes, 0), Ok((4..7, 11)));
+        assert_eq!(format.read_part_back(&bytes, 11), Ok((4..7, 0)));
+
+        let mut corrupted = bytes;
+        corrupted[7] = 2;
+        assert_eq!(
+            format.read_part(&corrupted, 0),
+            Err(SplitBufferError::LengthMismatch { offset: 0 })
+        );
+        corrupted[7] = 100;
+        assert_eq!(
+            format.read_part_back(&corrupted, 11),
+            Err(SplitBufferError::LengthMismatch { offset: 11 })
+        );
+        assert_eq!(
+            format.read_part(&bytes[..10], 0),
+            Err(SplitBufferError::TruncatedLength { offset: 7 })
+        );
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(
+            Format::default().with_trailing_lengths(true).read_part_back(&[0xff; 8], 8),
+            Err(SplitBufferError::LengthMismatch { offset: 8 })
+        );
+
+        let varint = Format::new(LengthEncoding::Varint).with_trailing_lengths(true);
+        assert_eq!(varint.read_part(&[2, b'a', b'b', 2], 0), Ok((1..3, 4)));
+        assert_eq!(
+            varint.read_part(&[2, b'a', b'b', 1], 0),
+            Err(SplitBufferError::LengthMismatch { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn header_round_trip() {
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            for options in 0..8 {
+                let format = Format::new(lengths)
+                    .with_trailing_lengths(options & 1 != 0)
+                    .with_index(options & 2 != 0)
+                    .with_checksums(options & 4 != 0)
+                    .with_header(true);
+                let mut bytes = Vec::new();
+                format.write_header(&mut bytes);
+                assert_eq!(bytes.len(), format.header_len());
+                assert_eq!(Format::read_header(&bytes), Ok(format));
+                assert_eq!(Format::detect(&bytes), Ok(format));
+                assert_eq!(format.check_header(&bytes), Ok(()));
+            }
+        }
+        let mut bytes = Vec::new();
+        Format::default().write_header(&mut bytes);
+        assert!(bytes.is_empty());
+        assert_eq!(Format::detect(b"SBU"), Ok(Format::default()));
+        assert_eq!(Format::detect(&[]), Ok(Format::default()));
+    }
+
+    #[test]
+    fn header_errors() {
+        assert_eq!(Format::detect(b"SBUF\x01\x01\x00"), Err(SplitBufferError::InvalidHeader));
+        assert_eq!(
+            Format::read_header(b"SBUG\x01\x01\x00\x00"),
+            Err(SplitBufferError::InvalidHeader)
+        );
+        assert_eq!(Format::detect(b"SBUF\x01\x03\x00\x00"), Err(SplitBufferError::InvalidHeader));
+        assert_eq!(
+            Format::detect(b"SBUF\x02\x01\x00\x00"),
+            Err(SplitBufferError::UnsupportedVersion { version: 2 })
+        );
+        assert_eq!(
+            Format::detect(b"SBUF\x01\x01\x00\x80"),
+            Err(SplitBufferError::UnsupportedFlags { flags: 0x8000 })
+        );
+
+        let format = Format::new(LengthEncoding::Varint).with_header(true);
+        assert_eq!(
+            format.check_header(b"SBUF\x01\x01\x00\x00"),
+            Err(SplitBufferError::FormatMismatch {
+                expected: format,
+                found: Format::default().with_header(true)
+            })
+        );
+        assert_eq!(format.check_header(b"\x00abc"), Err(SplitBufferError::InvalidHeader));
+        assert_eq!(Format::default().check_header(b"\x00abc"), Ok(()));
+    }
+
+    #[test]
+    fn verify_checksum() {
+        let format = Format::new(LengthEncoding::U32).with_checksums(true);
+        let mut bytes = vec![3, 0, 0, 0, b'a', b'b', b'c'];
+        bytes.extend_from_slice(format.encode_suffix(b"abc").as_ref());
+        assert_eq!(bytes.len(), format.encoded_len(3));
+        assert_eq!(format.read_part(&bytes, 0), Ok((4..7, 11)));
+        assert!(format.verify_checksum(&bytes, 4..7));
+        bytes[5] ^= 1;
+        assert!(!format.verify_checksum(&bytes, 4..7));
+        assert!(Format::default().verify_checksum(&bytes, 4..7));
+    }
+
+    #[test]
+    fn checksum_must_fit() {
+        let format = Format::new(LengthEncoding::U32).with_checksums(true);
+        assert_eq!(
+            format.read_part(&[3, 0, 0, 0, b'a', b'b', b'c', 0, 0, 0], 0),
+            Err(SplitBufferError::PartOutOfBounds { offset: 0, len: 3, available: 6 })
+        );
+        // Adding the checksum to a huge length must not overflow.
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(
+            Format::default().with_checksums(true).read_part(&[0xff; 8], 0),
+            Err(SplitBufferError::PartOutOfBounds { offset: 0, len: usize::MAX, available: 0 })
+        );
+    }
+}
@@ -1,71 +1,1390 @@
-#[derive(Clone)]
-pub struct Buffer(Vec<u8>);
+#![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "futures")]
+mod async_reader;
+#[cfg(feature = "futures")]
+mod async_writer;
+mod buffer_ref;
+#[cfg(feature = "alloc")]
+mod builder;
+#[cfg(feature = "tokio")]
+mod codec;
+mod crc;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod decoder;
+mod error;
+mod format;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "std")]
+mod reader;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(feature = "bytes")]
+mod shared;
+mod slice_builder;
+#[cfg(all(test, feature = "alloc"))]
+mod testing;
+#[cfg(feature = "std")]
+mod writer;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::cmp::Ordering;
+use core::ops::Range;
+
+#[cfg(feature = "futures")]
+pub use async_reader::AsyncBufferReader;
+#[cfg(feature = "futures")]
+pub use async_writer::AsyncBufferWriter;
+pub use buffer_ref::BufferRef;
+#[cfg(feature = "alloc")]
+pub use builder::{BufferBuilder, PartWriter};
+#[cfg(feature = "tokio")]
+pub use codec::{BufferCodec, SplitBufferCodec};
+pub use error::{CapacityError, SplitBufferError};
+pub use format::{Format, LengthEncoding};
+#[cfg(feature = "mmap")]
+pub use mmap::MmapBuffer;
+#[cfg(feature = "rayon")]
+pub use parallel::ParIter;
+#[cfg(feature = "std")]
+pub use reader::BufferReader;
+#[cfg(feature = "bytes")]
+pub use shared::{SharedBuffer, SharedBufferIterator};
+pub use slice_builder::SliceBuilder;
+#[cfg(feature = "std")]
+pub use writer::{BufferWriter, WriteTotals};
+
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug)]
+pub struct Buffer {
+    data: Vec<u8>,
+    format: Format,
+    len: usize,
+    index: Option<Vec<usize>>,
+}
+
+#[cfg(feature = "alloc")]
 impl Buffer {
     /// Build a buffer from parts that resolve to a slice of byte slices.
     ///
     /// A size hint will be calculated from the parts to preallocate the buffer.
     pub fn build<T: AsRef<[U]>, U: AsRef<[u8]>>(parts: T) -> Self {
-        let parts = parts.as_ref();
-        let parts_len = parts.len();
-        let bytes_total = parts.into_iter().fold(0usize, |acc, part| acc + part.as_ref().len());
-        Self::build_with_size_hint(parts, (parts_len * std::mem::size_of::<usize>()) + bytes_total)
+        Self::build_with_format(parts, Format::default())
     }
 
     /// Build a buffer from parts that resolve to a slice of byte slices.
     pub fn build_with_size_hint<T: AsRef<[U]>, U: AsRef<[u8]>>(parts: T, size_hint: usize) -> Self {
+        Self::build_with_format_and_size_hint(parts, Format::default(), size_hint)
+    }
+
+    /// Build a buffer from parts using the given `Format`.
+    ///
+    /// The exact size of the buffer will be calculated from the parts to preallocate the buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format uses `LengthEncoding::U32` and a part is longer than `u32::MAX` bytes.
+    pub fn build_with_format<T: AsRef<[U]>, U: AsRef<[u8]>, F: Into<Format>>(
+        parts: T,
+        format: F,
+    ) -> Self {
+        let parts = parts.as_ref();
+        let format = format.into();
+        let size_hint = parts
+            .iter()
+            .fold(format.header_len(), |acc, part| acc + format.encoded_len(part.as_ref().len()));
+        Self::build_with_format_and_size_hint(parts, format, size_hint)
+    }
+
+    fn build_with_format_and_size_hint<T: AsRef<[U]>, U: AsRef<[u8]>>(
+        parts: T,
+        format: Format,
+        size_hint: usize,
+    ) -> Self {
         let parts = parts.as_ref();
 
         let mut buffer = Vec::with_capacity(size_hint);
+        format.write_header(&mut buffer);
 
         for part in parts {
-            let part = part.as_ref();
-            let part_len = part.len();
-            buffer.extend_from_slice(&part_len.to_le_bytes());
-            buffer.extend_from_slice(part);
+            format.write_part(&mut buffer, part.as_ref());
         }
 
         buffer.shrink_to_fit();
 
-        Buffer(buffer)
+        Buffer::from_raw_parts(buffer, format, parts.len())
+    }
+
+    pub(crate) fn from_raw_parts(data: Vec<u8>, format: Format, len: usize) -> Self {
+        let mut buffer = Buffer { data, format, len, index: None };
+        if format.is_indexed() {
+            buffer.build_index();
+        }
+        buffer
+    }
+
+    /// Create a buffer from bytes previously produced by `Buffer::into_inner`.
+    ///
+    /// The framing is validated using the `Format` recorded in the header, or the default
+    /// `Format` if there is no header.
+    ///
+    /// See `BufferRef::from_slice` for how bytes with an unreadable header are handled.
+    pub fn from_vec(vec: Vec<u8>) -> Result<Self, SplitBufferError> {
+        let view = BufferRef::from_slice(&vec)?;
+        let (format, len) = (view.format(), view.len());
+        Ok(Buffer::from_raw_parts(vec, format, len))
+    }
+
+    /// Create a buffer from bytes encoded with the given `Format`, validating the framing.
+    ///
+    /// If the format has a header, the header in `vec` must match it.
+    pub fn from_vec_with_format<F: Into<Format>>(
+        vec: Vec<u8>,
+        format: F,
+    ) -> Result<Self, SplitBufferError> {
+        let format = format.into();
+        let len = BufferRef::from_slice_with_format(&vec, format)?.len();
+        Ok(Buffer::from_raw_parts(vec, format, len))
+    }
+
+    /// Create a buffer from bytes without validating the framing.
+    ///
+    /// # Safety
+    ///
+    /// `vec` must be a correctly framed buffer with a header or in the default `Format`, such as
+    /// one returned by `Buffer::into_inner`.
+    pub unsafe fn from_vec_unchecked(vec: Vec<u8>) -> Self {
+        let format = Format::detect(&vec).unwrap_or_default();
+        Self::from_vec_with_format_unchecked(vec, format)
+    }
+
+    /// Create a buffer from bytes encoded with the given `Format` without validating the framing.
+    ///
+    /// # Safety
+    ///
+    /// `vec` must be a correctly framed buffer in `format`.
+    pub unsafe fn from_vec_with_format_unchecked<F: Into<Format>>(vec: Vec<u8>, format: F) -> Self {
+        let format = format.into();
+        let len = BufferRef::from_slice_with_format_unchecked(&vec, format).len();
+        Buffer::from_raw_parts(vec, format, len)
+    }
+
+    /// Get the `Format` used to encode the buffer
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get a borrowed `BufferRef` view of the buffer.
+    pub fn as_buffer_ref(&self) -> BufferRef<'_> {
+        BufferRef::from_raw_parts(&self.data, self.format, self.len, self.index.as_deref())
+    }
+
+    /// Get the encoded bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the number of parts.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the buffer has no parts.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the parts of the buffer.
+    pub fn iter(&self) -> BufferIterator<'_> {
+        self.as_buffer_ref().iter()
+    }
+
+    /// Iterate over the parts of the buffer, yielding an error if the framing is malformed or a
+    /// checksum does not match.
+    ///
+    /// Iteration stops after the first error.
+    pub fn try_iter(&self) -> TryIter<'_> {
+        self.as_buffer_ref().try_iter()
+    }
+
+    /// Check that every part of the buffer is framed correctly and matches its checksum.
+    pub fn validate(&self) -> Result<(), SplitBufferError> {
+        self.as_buffer_ref().validate()
+    }
+
+    /// Get the last part.
+    ///
+    /// This walks the buffer from the start unless the format has trailing lengths or the buffer
+    /// is indexed.
+    pub fn last(&self) -> Option<&[u8]> {
+        self.as_buffer_ref().last()
+    }
+
+    /// Build an index of part offsets so that `Buffer::get` runs in constant time.
+    ///
+    /// The index takes one `usize` per part.
+    pub fn build_index(&mut self) {
+        if self.index.is_none() {
+            self.index = Some(self.as_buffer_ref().offsets());
+        }
+    }
+
+    /// Check whether the buffer has an index of part offsets.
+    pub fn is_indexed(&self) -> bool {
+        self.index.is_some()
+    }
+
+    /// Get the part at `index`.
+    ///
+    /// This walks the buffer from the start unless `Buffer::build_index` has been called.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.as_buffer_ref().get(index)
+    }
+
+    /// Append a part.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format uses `LengthEncoding::U32` and the part is longer than `u32::MAX`
+    /// bytes.
+    pub fn push<T: AsRef<[u8]>>(&mut self, part: T) {
+        if let Some(ref mut index) = self.index {
+            index.push(self.data.len());
+        }
+        self.format.write_part(&mut self.data, part.as_ref());
+        self.len += 1;
+    }
+
+    /// Insert a part at `index`, shifting all parts after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`, or if the format uses `LengthEncoding::U32` and the part is
+    /// longer than `u32::MAX` bytes.
+    pub fn insert<T: AsRef<[u8]>>(&mut self, index: usize, part: T) {
+        assert!(
+            index <= self.len,
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            self.len
+        );
+        let offset = self.offset_of(index);
+        let encoded = self.encode(part.as_ref());
+        if let Some(ref mut offsets) = self.index {
+            offsets.insert(index, offset);
+        }
+        self.shift_offsets(index + 1, 0, encoded.len());
+        self.data.splice(offset..offset, encoded);
+        self.len += 1;
+    }
+
+    /// Remove and return the part at `index`, shifting all parts after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Vec<u8> {
+        let (range, span) = self.span_or_panic(index);
+        let part = self.data[range].to_vec();
+        if let Some(ref mut offsets) = self.index {
+            offsets.remove(index);
+        }
+        self.shift_offsets(index, span.len(), 0);
+        self.data.drain(span);
+        self.len -= 1;
+        part
     }
 
-    /// Get the inner `Vec<u8>`
+    /// Replace the part at `index`, returning the previous part.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds, or if the format uses `LengthEncoding::U32` and the
+    /// part is longer than `u32::MAX` bytes.
+    pub fn replace<T: AsRef<[u8]>>(&mut self, index: usize, part: T) -> Vec<u8> {
+        let (range, span) = self.span_or_panic(index);
+        let previous = self.data[range].to_vec();
+        let encoded = self.encode(part.as_ref());
+        self.shift_offsets(index + 1, span.len(), encoded.len());
+        self.data.splice(span, encoded);
+        previous
+    }
+
+    /// Keep the first `len` parts, dropping the rest.
+    ///
+    /// This has no effect if `len` is greater than or equal to the number of parts.
+    pub fn truncate(&mut self, len: usize) {
+        if let Some((_, span)) = self.as_buffer_ref().span(len) {
+            self.data.truncate(span.start);
+            if let Some(ref mut offsets) = self.index {
+                offsets.truncate(len);
+            }
+            self.len = len;
+        }
+    }
+
+    /// Remove all parts.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Move all parts of `other` to the end of this buffer, leaving `other` empty.
+    ///
+    /// If both buffers frame their parts the same way, this is a plain byte copy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this buffer's format uses `LengthEncoding::U32` and a part of `other` is longer
+    /// than `u32::MAX` bytes.
+    pub fn append(&mut self, other: &mut Buffer) {
+        self.extend_from_buffer(other);
+        other.clear();
+    }
+
+    /// Concatenate the parts of `buffers` into a new buffer.
+    ///
+    /// The new buffer uses the format of the first buffer, or the default `Format` if there are
+    /// none. Buffers framed the same way are copied as bytes, others are re-encoded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the first buffer's format uses `LengthEncoding::U32` and a part of another
+    /// buffer is longer than `u32::MAX` bytes.
+    pub fn concat(buffers: &[Buffer]) -> Buffer {
+        let format = buffers.first().map(Buffer::format).unwrap_or_default();
+        let mut data = Vec::with_capacity(buffers.iter().map(|buffer| buffer.data.len()).sum());
+        format.write_header(&mut data);
+        let index = if format.is_indexed() { Some(Vec::new()) } else { None };
+        let mut concatenated = Buffer { data, format, len: 0, index };
+        for buffer in buffers {
+            concatenated.extend_from_buffer(buffer);
+        }
+        concatenated
+    }
+
+    /// Split the buffer in two at part `at`, returning the parts from `at` onwards.
+    ///
+    /// The returned buffer has the same format.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> Buffer {
+        assert!(at <= self.len, "`at` split index (is {}) should be <= len (is {})", at, self.len);
+        let offset = self.offset_of(at);
+        let header_len = self.format.header_len();
+
+        let mut data = Vec::with_capacity(header_len + self.data.len() - offset);
+        data.extend_from_slice(&self.data[..header_len]);
+        data.extend_from_slice(&self.data[offset..]);
+        self.data.truncate(offset);
+
+        let index = self.index.as_mut().map(|offsets| {
+            offsets.split_off(at).into_iter().map(|o| o - offset + header_len).collect()
+        });
+        let other = Buffer { data, format: self.format, len: self.len - at, index };
+        self.len = at;
+        other
+    }
+
+    /// Borrow the parts before `at` and the parts from `at` onwards as two views.
+    ///
+    /// The bytes of the second view do not start with a header, so its format never has one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    pub fn split_at(&self, at: usize) -> (BufferRef<'_>, BufferRef<'_>) {
+        assert!(at <= self.len, "`at` split index (is {}) should be <= len (is {})", at, self.len);
+        let offset = self.offset_of(at);
+        let (front, back) = self.data.split_at(offset);
+        let index = self.index.as_ref().map(|offsets| &offsets[..at]);
+        (
+            BufferRef::from_raw_parts(front, self.format, at, index),
+            BufferRef::from_raw_parts(back, self.format.with_header(false), self.len - at, None),
+        )
+    }
+
+    /// Keep only the parts for which `f` returns `true`.
+    ///
+    /// Parts are compacted in place without allocating.
+    pub fn retain<F: FnMut(&[u8]) -> bool>(&mut self, mut f: F) {
+        self.compact(|data, part, _| f(&data[part]));
+    }
+
+    /// Remove consecutive repeated parts.
+    pub fn dedup(&mut self) {
+        self.dedup_by(|a, b| a == b);
+    }
+
+    /// Remove consecutive parts for which `same_bucket(part, previous)` returns `true`.
+    ///
+    /// Parts are compacted in place without allocating.
+    pub fn dedup_by<F: FnMut(&[u8], &[u8]) -> bool>(&mut self, mut same_bucket: F) {
+        self.compact(|data, part, previous| match previous {
+            Some(previous) => !same_bucket(&data[part], &data[previous]),
+            None => true,
+        });
+    }
+
+    /// Sort the parts.
+    pub fn sort(&mut self) {
+        self.sort_by(Ord::cmp);
+    }
+
+    /// Sort the parts with a comparator function.
+    pub fn sort_by<F: FnMut(&[u8], &[u8]) -> Ordering>(&mut self, mut compare: F) {
+        self.reorder(|data, spans| {
+            spans.sort_by(|a, b| compare(&data[a.0.clone()], &data[b.0.clone()]))
+        });
+    }
+
+    /// Sort the parts with a key extraction function.
+    pub fn sort_by_key<K: Ord, F: FnMut(&[u8]) -> K>(&mut self, mut f: F) {
+        self.reorder(|data, spans| spans.sort_by_key(|span| f(&data[span.0.clone()])));
+    }
+
+    /// Sort the parts without preserving the order of equal parts.
+    pub fn sort_unstable(&mut self) {
+        self.sort_unstable_by(Ord::cmp);
+    }
+
+    /// Sort the parts with a comparator function without preserving the order of equal parts.
+    pub fn sort_unstable_by<F: FnMut(&[u8], &[u8]) -> Ordering>(&mut self, mut compare: F) {
+        self.reorder(|data, spans| {
+            spans.sort_unstable_by(|a, b| compare(&data[a.0.clone()], &data[b.0.clone()]))
+        });
+    }
+
+    /// Sort the parts with a key extraction function without preserving the order of equal parts.
+    pub fn sort_unstable_by_key<K: Ord, F: FnMut(&[u8]) -> K>(&mut self, mut f: F) {
+        self.reorder(|data, spans| spans.sort_unstable_by_key(|span| f(&data[span.0.clone()])));
+    }
+
+    /// Move the parts for which `keep(data, part, previous)` returns `true` to the front, in order.
+    ///
+    /// `part` is the range of the bytes of the current part and `previous` is the range of the
+    /// last part kept so far.
+    fn compact<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&[u8], Range<usize>, Option<Range<usize>>) -> bool,
+    {
+        let mut read = self.format.header_len();
+        let mut write = read;
+        let mut previous = None;
+        let mut len = 0;
+        while read < self.data.len() {
+            let (part, next) =
+                self.format.read_part(&self.data, read).expect("Buffer must be correctly framed");
+            if keep(&self.data, part.clone(), previous.clone()) {
+                self.data.copy_within(read..next, write);
+                previous = Some(part.start - read + write..part.end - read + write);
+                write += next - read;
+                len += 1;
+            }
+            read = next;
+        }
+        self.data.truncate(write);
+        self.len = len;
+        self.rebuild_index();
+    }
+
+    /// Rewrite the parts in the order given by `sort`, which reorders the locations of the parts.
+    fn reorder<F: FnOnce(&[u8], &mut Vec<(Range<usize>, Range<usize>)>)>(&mut self, sort: F) {
+        let mut spans = self.as_buffer_ref().spans().collect();
+        sort(&self.data, &mut spans);
+
+        let mut data = Vec::with_capacity(self.data.len());
+        data.extend_from_slice(&self.data[..self.format.header_len()]);
+        for (_, span) in spans {
+            data.extend_from_slice(&self.data[span]);
+        }
+        self.data = data;
+        self.rebuild_index();
+    }
+
+    fn rebuild_index(&mut self) {
+        if self.index.is_some() {
+            let offsets = self.as_buffer_ref().offsets();
+            self.index = Some(offsets);
+        }
+    }
+
+    /// Append the parts of `other`, copying its bytes if it frames parts the same way.
+    fn extend_from_buffer(&mut self, other: &Buffer) {
+        if !self.format.same_framing(&other.format) {
+            self.extend(other);
+            return;
+        }
+
+        let header_len = other.format.header_len();
+        let start = self.data.len();
+        if let Some(ref mut offsets) = self.index {
+            let spans = other.as_buffer_ref().spans();
+            offsets.extend(spans.map(|(_, span)| span.start - header_len + start));
+        }
+        self.data.extend_from_slice(&other.data[header_len..]);
+        self.len += other.len;
+    }
+
+    /// Get the offset of part `index`, or the end of the data if `index == len`.
+    fn offset_of(&self, index: usize) -> usize {
+        match self.as_buffer_ref().span(index) {
+            Some((_, span)) => span.start,
+            None => self.data.len(),
+        }
+    }
+
+    fn span_or_panic(&self, index: usize) -> (Range<usize>, Range<usize>) {
+        match self.as_buffer_ref().span(index) {
+            Some(span) => span,
+            None => panic!("index (is {}) should be < len (is {})", index, self.len),
+        }
+    }
+
+    fn encode(&self, part: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(self.format.encoded_len(part.len()));
+        self.format.write_part(&mut encoded, part);
+        encoded
+    }
+
+    /// Update the offsets of parts from `start` onwards after `removed` bytes were replaced with
+    /// `added` bytes before them.
+    fn shift_offsets(&mut self, start: usize, removed: usize, added: usize) {
+        if let Some(ref mut offsets) = self.index {
+            for offset in &mut offsets[start..] {
+                *offset = *offset - removed + added;
+            }
+        }
+    }
+
+    /// Get the inner `Vec<u8>`, including the header if the format has one
     pub fn into_inner(self) -> Vec<u8> {
-        self.0
+        self.data
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::convert::TryFrom<Vec<u8>> for Buffer {
+    type Error = SplitBufferError;
+
+    fn try_from(vec: Vec<u8>) -> Result<Self, Self::Error> {
+        Buffer::from_vec(vec)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: AsRef<[u8]>> core::iter::FromIterator<T> for Buffer {
+    fn from_iter<I: IntoIterator<Item = T>>(parts: I) -> Self {
+        let mut builder = BufferBuilder::new();
+        builder.extend(parts);
+        builder.finish()
+    }
+}
+
+/// Appends parts in the buffer's own `Format`, keeping the index up to date if there is one.
+///
+/// # Panics
+///
+/// Panics if the format uses `LengthEncoding::U32` and a part is longer than `u32::MAX` bytes.
+#[cfg(feature = "alloc")]
+impl<T: AsRef<[u8]>> Extend<T> for Buffer {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, parts: I) {
+        for part in parts {
+            self.push(part);
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl AsRef<[u8]> for Buffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
     }
 }
 
 /// Iterator over parts of a `Buffer`
+///
+/// Iteration ends early if the framing is malformed and checksums are not verified; use
+/// `Buffer::try_iter` to observe errors.
+///
+/// Iterating from the back walks the remaining parts on each step unless the format has trailing
+/// lengths or the buffer is indexed.
 pub struct BufferIterator<'a> {
     buffer: &'a [u8],
+    format: Format,
+    index: Option<&'a [usize]>,
     offset: usize,
+    back: usize,
+    position: usize,
+    remaining: usize,
 }
 
+#[cfg(feature = "alloc")]
 impl<'a> IntoIterator for &'a Buffer {
     type Item = &'a [u8];
     type IntoIter = BufferIterator<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        BufferIterator { buffer: &self.0, offset: 0 }
+        self.iter()
+    }
+}
+
+impl<'a> BufferIterator<'a> {
+    fn new(buffer: &'a [u8], format: Format, remaining: usize, index: Option<&'a [usize]>) -> Self {
+        BufferIterator {
+            buffer,
+            format,
+            index,
+            offset: format.header_len(),
+            back: buffer.len(),
+            position: 0,
+            remaining,
+        }
+    }
+
+    fn try_next(&mut self, verify: bool) -> Option<Result<&'a [u8], SplitBufferError>> {
+        if self.remaining == 0 || self.offset >= self.back {
+            return None;
+        }
+
+        let part = self.format.read_part(self.buffer, self.offset).and_then(|(range, next)| {
+            self.verify(verify, &range, self.offset, self.position)?;
+            Ok((range, next))
+        });
+
+        match part {
+            Ok((range, next)) => {
+                self.offset = next;
+                self.position += 1;
+                self.remaining -= 1;
+                Some(Ok(&self.buffer[range]))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+
+    fn try_next_back(&mut self, verify: bool) -> Option<Result<&'a [u8], SplitBufferError>> {
+        if self.remaining == 0 || self.offset >= self.back {
+            return None;
+        }
+
+        let part = if self.format.has_trailing_lengths() {
+            self.format.read_part_back(self.buffer, self.back)
+        } else {
+            self.last_offset().and_then(|offset| {
+                let (range, _) = self.format.read_part(self.buffer, offset)?;
+                Ok((range, offset))
+            })
+        }
+        .and_then(|(range, offset)| {
+            self.verify(verify, &range, offset, self.position + self.remaining - 1)?;
+            Ok((range, offset))
+        });
+
+        match part {
+            Ok((range, offset)) => {
+                self.back = offset;
+                self.remaining -= 1;
+                Some(Ok(&self.buffer[range]))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Check the checksum of part number `index` starting at `offset` if `verify` is set.
+    fn verify(
+        &self,
+        verify: bool,
+        data: &Range<usize>,
+        offset: usize,
+        index: usize,
+    ) -> Result<(), SplitBufferError> {
+        if verify && !self.format.verify_checksum(self.buffer, data.clone()) {
+            return Err(SplitBufferError::ChecksumMismatch { index, offset });
+        }
+        Ok(())
+    }
+
+    /// Find the offset of the last remaining part without using trailing lengths.
+    fn last_offset(&self) -> Result<usize, SplitBufferError> {
+        if let Some(offset) =
+            self.index.and_then(|index| index.get(self.position + self.remaining - 1))
+        {
+            return Ok(*offset);
+        }
+
+        let mut offset = self.offset;
+        for _ in 1..self.remaining {
+            offset = self.format.read_part(self.buffer, offset)?.1;
+        }
+        Ok(offset)
     }
 }
 
 impl<'a> Iterator for BufferIterator<'a> {
     type Item = &'a [u8];
+
     fn next(&mut self) -> Option<Self::Item> {
-        use std::convert::TryInto;
+        self.try_next(false)?.ok()
+    }
 
-        if self.buffer[self.offset..].is_empty() {
-            return None;
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> DoubleEndedIterator for BufferIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.try_next_back(false)?.ok()
+    }
+}
+
+impl<'a> ExactSizeIterator for BufferIterator<'a> {}
+
+/// Fallible iterator over parts of a `Buffer`
+///
+/// Checksums are verified if the format has them.
+pub struct TryIter<'a> {
+    inner: BufferIterator<'a>,
+}
+
+impl<'a> Iterator for TryIter<'a> {
+    type Item = Result<&'a [u8], SplitBufferError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.try_next(true)
+    }
+}
+
+impl<'a> DoubleEndedIterator for TryIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.try_next_back(true)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::convert::TryFrom;
+
+    use super::*;
+    use crate::testing::{formats, parts};
+
+    fn encoded<F: Into<Format>>(parts: &[&[u8]], format: F) -> Vec<u8> {
+        Buffer::build_with_format(parts, format).into_inner()
+    }
+
+    /// Xorshift generator, so that model tests are reproducible without extra dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        /// Generate a part, with lengths around the varint boundary and plenty of duplicates.
+        fn part(&mut self) -> Vec<u8> {
+            let len = [0, 1, 2, 3, 127, 128, 300][self.below(7)];
+            vec![b'a' + self.below(3) as u8; len]
+        }
+
+        fn parts(&mut self, max: usize) -> Vec<Vec<u8>> {
+            let len = self.below(max + 1);
+            (0..len).map(|_| self.part()).collect()
+        }
+    }
+
+    /// Every combination of format options, each with and without an index built after
+    /// decoding rather than recorded in the format.
+    fn cases() -> Vec<(Format, bool)> {
+        formats()
+            .into_iter()
+            .map(|format| (format.with_index(false), format.is_indexed()))
+            .collect()
+    }
+
+    fn model_buffer(model: &[Vec<u8>], format: Format, indexed: bool) -> Buffer {
+        let mut buffer = Buffer::build_with_format(model, format);
+        if indexed {
+            buffer.build_index();
+        }
+        buffer
+    }
+
+    fn check_ref(buffer: BufferRef<'_>, model: &[Vec<u8>]) {
+        assert_eq!(buffer.len(), model.len());
+        for (i, part) in model.iter().enumerate() {
+            assert_eq!(buffer.get(i), Some(&part[..]), "part {}", i);
+        }
+        assert_eq!(buffer.get(model.len()), None);
+        assert_eq!(buffer.last(), model.last().map(|part| &part[..]));
+        assert!(buffer.iter().eq(model.iter().map(|part| &part[..])));
+        assert!(buffer.iter().rev().eq(model.iter().rev().map(|part| &part[..])));
+        assert!(buffer
+            .try_iter()
+            .rev()
+            .map(Result::unwrap)
+            .eq(model.iter().rev().map(|part| &part[..])));
+        assert_eq!(buffer.validate(), Ok(()));
+    }
+
+    /// Check a buffer against its model, including the index and the encoded bytes.
+    fn check(buffer: &Buffer, model: &[Vec<u8>]) {
+        check_ref(buffer.as_buffer_ref(), model);
+        let decoded =
+            BufferRef::from_slice_with_format(buffer.as_bytes(), buffer.format()).unwrap();
+        assert_eq!(decoded.len(), model.len());
+        if let Some(ref index) = buffer.index {
+            assert_eq!(index, &decoded.offsets());
+        }
+    }
+
+    #[test]
+    fn fixed_width_lengths() {
+        let parts: &[&[u8]] = &[b"abc", b"", b"de"];
+        assert_eq!(
+            Buffer::build_with_format(parts, LengthEncoding::U32).into_inner(),
+            b"\x03\0\0\0abc\0\0\0\0\x02\0\0\0de"
+        );
+        let buffer = Buffer::build(parts);
+        assert_eq!(buffer.format(), Format::new(LengthEncoding::U64));
+        assert!(buffer.into_iter().eq(parts.iter().copied()));
+        assert_eq!(
+            buffer.into_inner(),
+            b"\x03\0\0\0\0\0\0\0abc\0\0\0\0\0\0\0\0\x02\0\0\0\0\0\0\0de"
+        );
+    }
+
+    #[test]
+    fn varint_lengths() {
+        let parts: &[&[u8]] = &[b"abc", &[b'x'; 128]];
+        let data = Buffer::build_with_format(parts, LengthEncoding::Varint).into_inner();
+        assert_eq!(data.len(), 1 + 3 + 2 + 128);
+        assert_eq!(&data[..6], b"\x03abc\x80\x01");
+    }
+
+    #[test]
+    fn round_trip_every_encoding() {
+        let parts: &[&[u8]] = &[b"", &[0xff; 300], b"x"];
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            let buffer = Buffer::build_with_format(parts, lengths);
+            assert_eq!(buffer.format().lengths(), lengths);
+            assert!(buffer.into_iter().eq(parts.iter().copied()));
+        }
+    }
+
+    #[test]
+    fn try_iter_stops_after_error() {
+        let format = Format::new(LengthEncoding::U32);
+        let mut data = encoded(&[b"abc", b"de", b"f"], format);
+        data[7] = 9;
+        let buffer = Buffer { data, format, len: 3, index: None };
+        let parts: Vec<_> = buffer.try_iter().collect();
+        assert_eq!(
+            parts,
+            vec![
+                Ok(&b"abc"[..]),
+                Err(SplitBufferError::PartOutOfBounds { offset: 7, len: 9, available: 7 })
+            ]
+        );
+        assert_eq!(buffer.iter().collect::<Vec<_>>(), vec![&b"abc"[..]]);
+        assert_eq!(
+            buffer.validate(),
+            Err(SplitBufferError::PartOutOfBounds { offset: 7, len: 9, available: 7 })
+        );
+    }
+
+    #[test]
+    fn validate_truncated() {
+        let format = Format::new(LengthEncoding::Varint);
+        let data = encoded(&[&[0; 200], b"de"], format);
+        assert_eq!(Buffer { data: data.clone(), format, len: 2, index: None }.validate(), Ok(()));
+        assert_eq!(
+            Buffer { data: data[..data.len() - 1].to_vec(), format, len: 2, index: None }
+                .validate(),
+            Err(SplitBufferError::PartOutOfBounds { offset: 202, len: 2, available: 1 })
+        );
+        assert_eq!(
+            Buffer { data: data[..1].to_vec(), format, len: 2, index: None }.validate(),
+            Err(SplitBufferError::TruncatedLength { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn from_vec_round_trip() {
+        let parts: &[&[u8]] = &[b"abc", b"", b"de"];
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            let data = encoded(parts, lengths);
+            let buffer = Buffer::from_vec_with_format(data.clone(), lengths).unwrap();
+            assert_eq!(buffer.format(), lengths.into());
+            assert!(buffer.iter().eq(parts.iter().copied()));
+            assert_eq!(buffer.into_inner(), data);
+        }
+        let buffer = Buffer::try_from(encoded(parts, Format::default())).unwrap();
+        assert!(buffer.iter().eq(parts.iter().copied()));
+        assert!(Buffer::from_vec(Vec::new()).unwrap().iter().next().is_none());
+    }
+
+    #[test]
+    fn from_vec_truncated() {
+        let data = encoded(&[b"abc", b"de"], Format::default());
+        assert_eq!(
+            Buffer::from_vec(data[..data.len() - 1].to_vec()).unwrap_err(),
+            SplitBufferError::PartOutOfBounds { offset: 11, len: 2, available: 1 }
+        );
+        assert_eq!(
+            Buffer::from_vec(data[..15].to_vec()).unwrap_err(),
+            SplitBufferError::TruncatedLength { offset: 11 }
+        );
+        assert!(Buffer::from_vec(data[..11].to_vec()).unwrap().iter().eq([&b"abc"[..]]));
+    }
+
+    #[test]
+    fn from_vec_oversized_prefix() {
+        let mut data = encoded(&[b"abc", b""], LengthEncoding::Varint);
+        data[4] = 3;
+        assert_eq!(
+            Buffer::from_vec_with_format(data.clone(), LengthEncoding::Varint).unwrap_err(),
+            SplitBufferError::PartOutOfBounds { offset: 4, len: 3, available: 0 }
+        );
+        let buffer =
+            unsafe { Buffer::from_vec_with_format_unchecked(data, LengthEncoding::Varint) };
+        assert_eq!(buffer.iter().count(), 1);
+        assert!(buffer.validate().is_err());
+    }
+
+    #[test]
+    fn get_with_and_without_index() {
+        let parts: Vec<Vec<u8>> = (0..50).map(|i| vec![i as u8; i * 7]).collect();
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            let mut buffer = Buffer::build_with_format(&parts, lengths);
+            assert!(!buffer.is_indexed());
+            let unindexed: Vec<_> =
+                (0..=parts.len()).map(|i| buffer.get(i).map(<[u8]>::to_vec)).collect();
+
+            buffer.build_index();
+            assert!(buffer.is_indexed());
+            assert_eq!(buffer.index.as_ref().unwrap().len(), parts.len());
+            for (i, part) in parts.iter().enumerate() {
+                assert_eq!(buffer.get(i), Some(&part[..]));
+                assert_eq!(unindexed[i].as_ref(), Some(part));
+            }
+            assert_eq!(buffer.get(parts.len()), None);
+            assert_eq!(unindexed[parts.len()], None);
+            assert_eq!(buffer.as_buffer_ref().get(3), Some(&parts[3][..]));
+        }
+    }
+
+    #[test]
+    fn len_and_exact_size() {
+        let parts: &[&[u8]] = &[b"abc", b"", b"de", b""];
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            let buffer = Buffer::build_with_format(parts, lengths);
+            assert_eq!(buffer.len(), 4);
+            assert!(!buffer.is_empty());
+            let mut iter = buffer.iter();
+            for remaining in (0..4).rev() {
+                iter.next().unwrap();
+                assert_eq!(iter.len(), remaining);
+            }
+            assert_eq!(iter.next(), None);
+
+            let decoded = Buffer::from_vec_with_format(buffer.into_inner(), lengths).unwrap();
+            assert_eq!(decoded.len(), 4);
+            assert_eq!(decoded.as_buffer_ref().len(), 4);
+            assert_eq!(decoded.iter().size_hint(), (4, Some(4)));
         }
+        let empty = Buffer::build::<&[&[u8]], &[u8]>(&[]);
+        assert!(empty.is_empty());
+        assert!(Buffer::from_vec(Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reverse_iteration() {
+        let parts: Vec<Vec<u8>> = (0..20).map(|i| vec![b'a' + i as u8; i * 13]).collect();
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            for &trailing in &[false, true] {
+                let format = Format::new(lengths).with_trailing_lengths(trailing);
+                let mut buffer = Buffer::build_with_format(&parts, format);
+                for _ in 0..2 {
+                    assert!(buffer.iter().rev().eq(parts.iter().rev().map(|part| &part[..])));
+                    assert_eq!(buffer.as_buffer_ref().last(), parts.last().map(|part| &part[..]));
+
+                    // Alternate ends until the iterators meet in the middle.
+                    let mut iter = buffer.iter();
+                    for i in 0..parts.len() / 2 {
+                        assert_eq!(iter.next(), Some(&parts[i][..]));
+                        assert_eq!(iter.next_back(), Some(&parts[parts.len() - 1 - i][..]));
+                        assert_eq!(iter.len(), parts.len() - 2 * (i + 1));
+                    }
+                    assert_eq!(iter.next_back(), None);
+                    assert_eq!(iter.next(), None);
+                    buffer.build_index();
+                }
+                let decoded = Buffer::from_vec_with_format(buffer.into_inner(), format).unwrap();
+                assert!(decoded
+                    .try_iter()
+                    .rev()
+                    .map(Result::unwrap)
+                    .eq(parts.iter().rev().map(|part| &part[..])));
+            }
+        }
+    }
+
+    #[test]
+    fn trailer_mismatch() {
+        let format = Format::new(LengthEncoding::U32).with_trailing_lengths(true);
+        let mut data = encoded(&[b"abc", b"de"], format);
+        let last = data.len() - 4;
+        data[last] = 3;
+        assert_eq!(
+            Buffer::from_vec_with_format(data.clone(), format).unwrap_err(),
+            SplitBufferError::LengthMismatch { offset: 11 }
+        );
+
+        data[last] = 100;
+        let buffer = Buffer { data, format, len: 2, index: None };
+        assert_eq!(
+            buffer.try_iter().next_back(),
+            Some(Err(SplitBufferError::LengthMismatch { offset: 21 }))
+        );
+        assert_eq!(buffer.iter().next_back(), None);
+    }
+
+    #[test]
+    fn collect_and_extend() {
+        let parts: Vec<Vec<u8>> = (0..10).map(|i| vec![i as u8; i]).collect();
+        let buffer: Buffer = parts.iter().collect();
+        assert_eq!(buffer.format(), Format::default());
+        assert_eq!(buffer.as_bytes(), Buffer::build(&parts).as_bytes());
+        assert_eq!(buffer.len(), parts.len());
+
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            let format = Format::new(lengths).with_trailing_lengths(true);
+            for &indexed in &[false, true] {
+                let mut buffer = Buffer::build_with_format(&parts[..4], format);
+                if indexed {
+                    buffer.build_index();
+                }
+                buffer.extend(&parts[4..]);
+                assert_eq!(buffer.is_indexed(), indexed);
+                assert_eq!(buffer.len(), parts.len());
+                assert_eq!(buffer.as_bytes(), Buffer::build_with_format(&parts, format).as_bytes());
+                for (i, part) in parts.iter().enumerate() {
+                    assert_eq!(buffer.get(i), Some(&part[..]));
+                }
+                assert!(buffer.iter().rev().eq(parts.iter().rev().map(|part| &part[..])));
+            }
+        }
+    }
+
+    #[test]
+    fn from_vec_header() {
+        let format = Format::new(LengthEncoding::U32).with_header(true);
+        let data = encoded(&[b"abc"], format);
+        assert_eq!(&data[..4], b"SBUF");
+        assert_eq!(Buffer::from_vec(data.clone()).unwrap().format(), format);
+        assert_eq!(unsafe { Buffer::from_vec_unchecked(data.clone()) }.format(), format);
+        assert_eq!(
+            Buffer::from_vec_with_format(data.clone(), format.with_trailing_lengths(true))
+                .unwrap_err(),
+            SplitBufferError::FormatMismatch {
+                expected: format.with_trailing_lengths(true),
+                found: format
+            }
+        );
+        assert_eq!(
+            Buffer::from_vec_with_format(data[..7].to_vec(), format).unwrap_err(),
+            SplitBufferError::InvalidHeader
+        );
+        assert_eq!(
+            Buffer::from_vec(data[..7].to_vec()).unwrap_err(),
+            SplitBufferError::InvalidHeader
+        );
+    }
+
+    #[test]
+    fn from_vec_unsupported_header() {
+        let format = Format::default().with_header(true);
+        let mut data = encoded(&[b"abc", b"de"], format);
+        data[4] = 2;
+        let version = SplitBufferError::UnsupportedVersion { version: 2 };
+        assert_eq!(Buffer::from_vec(data.clone()).unwrap_err(), version);
+        assert_eq!(BufferRef::from_slice(&data).unwrap_err(), version);
+        assert_eq!(Buffer::from_vec_with_format(data.clone(), format).unwrap_err(), version);
+
+        data[4] = 1;
+        data[7] = 0x80;
+        let flags = SplitBufferError::UnsupportedFlags { flags: 0x8000 };
+        assert_eq!(Buffer::from_vec(data.clone()).unwrap_err(), flags);
+        assert_eq!(Buffer::try_from(data).unwrap_err(), flags);
+    }
+
+    #[test]
+    fn header_is_skipped() {
+        let parts: &[&[u8]] = &[b"abc", b"", b"de"];
+        for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+            let format = Format::new(lengths).with_trailing_lengths(true).with_header(true);
+            let buffer = Buffer::from_vec(encoded(parts, format)).unwrap();
+            assert_eq!(buffer.format(), format);
+            assert_eq!(buffer.len(), 3);
+            assert!(buffer.iter().eq(parts.iter().copied()));
+            assert!(buffer.iter().rev().eq(parts.iter().rev().copied()));
+            assert_eq!(buffer.get(0), Some(&b"abc"[..]));
+
+            let mut builder = BufferBuilder::with_format(format);
+            builder.extend(parts);
+            assert_eq!(builder.finish().as_bytes(), buffer.as_bytes());
 
-        let bytes_start = self.offset + std::mem::size_of::<usize>();
-        let len = usize::from_le_bytes(
-            self.buffer[self.offset..bytes_start].try_into().expect("Must be `usize`"),
-        ) as usize;
-        self.offset = bytes_start + len;
+            let empty = BufferBuilder::with_format(format).finish();
+            assert_eq!(empty.as_bytes().len(), 8);
+            assert!(empty.is_empty());
+            assert_eq!(Buffer::from_vec(empty.into_inner()).unwrap().format(), format);
+        }
+    }
+
+    #[test]
+    fn indexed_format() {
+        let parts: &[&[u8]] = &[b"abc", b"", b"de"];
+        let format = Format::new(LengthEncoding::Varint).with_index(true).with_header(true);
+        let buffer = Buffer::build_with_format(parts, format);
+        assert!(buffer.is_indexed());
+        let loaded = Buffer::from_vec(buffer.into_inner()).unwrap();
+        assert_eq!(loaded.format(), format);
+        assert!(loaded.is_indexed());
+        assert_eq!(loaded.index, Some(vec![8, 12, 13]));
+        assert_eq!(loaded.get(2), Some(&b"de"[..]));
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let format = Format::new(LengthEncoding::U32).with_checksums(true);
+        let mut data = encoded(&[b"abc", b"de", b"f"], format);
+        assert_eq!(Buffer::from_vec_with_format(data.clone(), format).unwrap().len(), 3);
+        data[15] ^= 1;
+        assert_eq!(
+            Buffer::from_vec_with_format(data.clone(), format).unwrap_err(),
+            SplitBufferError::ChecksumMismatch { index: 1, offset: 11 }
+        );
+
+        let buffer = unsafe { Buffer::from_vec_with_format_unchecked(data, format) };
+        assert_eq!(
+            buffer.validate(),
+            Err(SplitBufferError::ChecksumMismatch { index: 1, offset: 11 })
+        );
+        let mut parts = buffer.try_iter().rev();
+        assert_eq!(parts.next(), Some(Ok(&b"f"[..])));
+        assert_eq!(
+            parts.next(),
+            Some(Err(SplitBufferError::ChecksumMismatch { index: 1, offset: 11 }))
+        );
+        assert_eq!(parts.next(), None);
+        // Plain iteration does not verify checksums.
+        assert_eq!(buffer.iter().collect::<Vec<_>>(), vec![&b"abc"[..], &b"ee"[..], &b"f"[..]]);
+    }
+
+    #[test]
+    fn checksums_round_trip() {
+        let parts = parts();
+        for format in formats().into_iter().filter(|f| f.has_checksums() && f.has_header()) {
+            let buffer =
+                Buffer::from_vec(Buffer::build_with_format(&parts, format).into_inner()).unwrap();
+            assert_eq!(buffer.format(), format);
+            assert!(buffer.try_iter().map(Result::unwrap).eq(parts.iter().map(Vec::as_slice)));
+            assert!(buffer
+                .try_iter()
+                .rev()
+                .map(Result::unwrap)
+                .eq(parts.iter().rev().map(Vec::as_slice)));
+        }
+    }
 
-        Some(&self.buffer[bytes_start..self.offset])
+    #[test]
+    fn mutations_match_model() {
+        for (format, indexed) in cases() {
+            let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+            let mut model = rng.parts(4);
+            let mut buffer = model_buffer(&model, format, indexed);
+            check(&buffer, &model);
+            for _ in 0..200 {
+                match rng.below(10) {
+                    0 | 1 => {
+                        let part = rng.part();
+                        buffer.push(&part);
+                        model.push(part);
+                    }
+                    2 | 3 => {
+                        let index = rng.below(model.len() + 1);
+                        let part = rng.part();
+                        buffer.insert(index, &part);
+                        model.insert(index, part);
+                    }
+                    4 | 5 if !model.is_empty() => {
+                        let index = rng.below(model.len());
+                        assert_eq!(buffer.remove(index), model.remove(index));
+                    }
+                    6 | 7 if !model.is_empty() => {
+                        let index = rng.below(model.len());
+                        let part = rng.part();
+                        assert_eq!(
+                            buffer.replace(index, &part),
+                            core::mem::replace(&mut model[index], part)
+                        );
+                    }
+                    8 => {
+                        let len = rng.below(model.len() + 2);
+                        buffer.truncate(len);
+                        model.truncate(len);
+                    }
+                    9 if rng.below(4) == 0 => {
+                        buffer.clear();
+                        model.clear();
+                    }
+                    _ => {}
+                }
+                check(&buffer, &model);
+            }
+            assert_eq!(buffer.is_indexed(), indexed);
+        }
+    }
+
+    #[test]
+    fn compaction_and_reordering_match_model() {
+        for (format, indexed) in cases() {
+            let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+            for op in 0..10 {
+                let mut model = rng.parts(40);
+                let mut buffer = model_buffer(&model, format, indexed);
+                match op {
+                    0 => {
+                        buffer.retain(|part| part.len() % 2 == 0);
+                        model.retain(|part| part.len() % 2 == 0);
+                    }
+                    1 => {
+                        buffer.dedup();
+                        model.dedup();
+                    }
+                    2 => {
+                        buffer.dedup_by(|a, b| a.len() == b.len());
+                        model.dedup_by(|a, b| a.len() == b.len());
+                    }
+                    3 => {
+                        buffer.sort();
+                        model.sort();
+                    }
+                    4 => {
+                        buffer.sort_by(|a, b| b.cmp(a));
+                        model.sort_by(|a, b| b.cmp(a));
+                    }
+                    5 => {
+                        buffer.sort_by_key(|part| part.len());
+                        model.sort_by_key(|part| part.len());
+                    }
+                    6 => {
+                        buffer.sort_unstable();
+                        model.sort_unstable();
+                    }
+                    7 => {
+                        buffer.sort_unstable_by(|a, b| b.cmp(a));
+                        model.sort_unstable_by(|a, b| b.cmp(a));
+                    }
+                    8 => {
+                        buffer.sort_unstable_by_key(|part| core::cmp::Reverse(part.to_vec()));
+                        model.sort_unstable_by_key(|part| core::cmp::Reverse(part.clone()));
+                    }
+                    _ => {
+                        buffer.retain(|_| false);
+                        model.clear();
+                    }
+                }
+                check(&buffer, &model);
+                assert_eq!(buffer.is_indexed(), indexed);
+
+                // Compacting must leave the buffer usable for further edits.
+                let part = rng.part();
+                buffer.insert(model.len() / 2, &part);
+                model.insert(model.len() / 2, part);
+                check(&buffer, &model);
+            }
+        }
+    }
+
+    #[test]
+    fn concatenation_and_splitting_match_model() {
+        let cases = cases();
+        for &(format, indexed) in &cases {
+            let mut rng = Rng(0xd1b5_4a32_d192_ed03);
+            for _ in 0..20 {
+                let mut model = rng.parts(20);
+                let mut buffer = model_buffer(&model, format, indexed);
+                let (other_format, other_indexed) = cases[rng.below(cases.len())];
+                let other_model = rng.parts(20);
+                let mut other = model_buffer(&other_model, other_format, other_indexed);
+                match rng.below(4) {
+                    0 => {
+                        buffer.append(&mut other);
+                        check(&other, &[]);
+                        model.extend(other_model);
+                        check(&buffer, &model);
+                    }
+                    1 => {
+                        let first = model_buffer(&model, format.with_index(indexed), false);
+                        let concatenated = Buffer::concat(&[first, other, buffer]);
+                        assert_eq!(concatenated.format(), format.with_index(indexed));
+                        assert_eq!(concatenated.is_indexed(), indexed);
+                        let mut expected = model.clone();
+                        expected.extend(other_model);
+                        expected.extend(model);
+                        check(&concatenated, &expected);
+                    }
+                    2 => {
+                        let at = rng.below(model.len() + 1);
+                        let mut tail = buffer.split_off(at);
+                        let tail_model = model.split_off(at);
+                        assert_eq!(tail.format(), format);
+                        assert_eq!(tail.is_indexed(), indexed);
+                        check(&buffer, &model);
+                        check(&tail, &tail_model);
+
+                        buffer.append(&mut tail);
+                        model.extend(tail_model);
+                        check(&buffer, &model);
+                    }
+                    _ => {
+                        let at = rng.below(model.len() + 1);
+                        let (front, back) = buffer.split_at(at);
+                        assert_eq!(back.format(), format.with_header(false));
+                        check_ref(front, &model[..at]);
+                        check_ref(back, &model[at..]);
+                    }
+                }
+            }
+        }
+        check(&Buffer::concat(&[]), &[]);
     }
 }
@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::vec::Vec;
+
+use memmap2::Mmap;
+
+use crate::{BufferIterator, BufferRef, Format, SplitBufferError, TryIter};
+
+/// Read-only buffer backed by a memory-mapped file
+///
+/// Parts are read straight from the mapping, so only the pages that are touched are loaded into
+/// memory.
+#[derive(Debug)]
+pub struct MmapBuffer {
+    mmap: Mmap,
+    format: Format,
+    len: usize,
+    index: Option<Vec<usize>>,
+}
+
+impl MmapBuffer {
+    /// Map the file at `path`, validating the framing and checksums upfront.
+    ///
+    /// The `Format` recorded in the header is used, or the default `Format` if there is no
+    /// header. Malformed files fail with `io::ErrorKind::InvalidData`, as does a file that starts
+    /// with the magic bytes but has an unsupported header, unless it is also a valid headerless
+    /// buffer in the default `Format`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mmap = map(path.as_ref())?;
+        let view = BufferRef::from_slice(&mmap)?;
+        let (format, len) = (view.format(), view.len());
+        Ok(Self::from_raw_parts(mmap, format, len))
+    }
+
+    /// Map the file at `path` encoded with the given `Format`, validating the framing and
+    /// checksums upfront.
+    ///
+    /// If the format has a header, the header in the file must match it.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped.
+    pub unsafe fn open_with_format<P: AsRef<Path>, F: Into<Format>>(
+        path: P,
+        format: F,
+    ) -> io::Result<Self> {
+        Self::from_mmap(map(path.as_ref())?, format.into())
+    }
+
+    /// Map the file at `path` without validating it, reading only the length prefixes needed to
+    /// count the parts.
+    ///
+    /// Use `MmapBuffer::validate` to check the framing and checksums later.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, and must be a correctly
+    /// framed buffer with a header or in the default `Format`.
+    pub unsafe fn open_unchecked<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mmap = map(path.as_ref())?;
+        let format = Format::detect(&mmap).unwrap_or_default();
+        let len = BufferRef::from_slice_with_format_unchecked(&mmap, format).len();
+        Ok(Self::from_raw_parts(mmap, format, len))
+    }
+
+    fn from_mmap(mmap: Mmap, format: Format) -> io::Result<Self> {
+        let len = BufferRef::from_slice_with_format(&mmap, format)?.len();
+        Ok(Self::from_raw_parts(mmap, format, len))
+    }
+
+    fn from_raw_parts(mmap: Mmap, format: Format, len: usize) -> Self {
+        let mut buffer = MmapBuffer { mmap, format, len, index: None };
+        if format.is_indexed() {
+            buffer.build_index();
+        }
+        buffer
+    }
+
+    /// Get the `Format` used to encode the buffer
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get a borrowed `BufferRef` view of the buffer.
+    pub fn as_buffer_ref(&self) -> BufferRef<'_> {
+        BufferRef::from_raw_parts(&self.mmap, self.format, self.len, self.index.as_deref())
+    }
+
+    /// Get the mapped bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    /// Get the number of parts.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the buffer has no parts.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the parts of the buffer.
+    pub fn iter(&self) -> BufferIterator<'_> {
+        self.as_buffer_ref().iter()
+    }
+
+    /// Iterate over the parts of the buffer, yielding an error if the framing is malformed or a
+    /// checksum does not match.
+    ///
+    /// Iteration stops after the first error.
+    pub fn try_iter(&self) -> TryIter<'_> {
+        self.as_buffer_ref().try_iter()
+    }
+
+    /// Check that every part of the buffer is framed correctly and matches its checksum.
+    pub fn validate(&self) -> Result<(), SplitBufferError> {
+        self.as_buffer_ref().validate()
+    }
+
+    /// Get the last part.
+    ///
+    /// This walks the buffer from the start unless the format has trailing lengths or the buffer
+    /// is indexed.
+    pub fn last(&self) -> Option<&[u8]> {
+        self.as_buffer_ref().last()
+    }
+
+    /// Build an index of part offsets so that `MmapBuffer::get` runs in constant time.
+    ///
+    /// The index takes one `usize` per part and is built automatically if the format is indexed.
+    pub fn build_index(&mut self) {
+        if self.index.is_none() {
+            self.index = Some(self.as_buffer_ref().offsets());
+        }
+    }
+
+    /// Check whether the buffer has an index of part offsets.
+    pub fn is_indexed(&self) -> bool {
+        self.index.is_some()
+    }
+
+    /// Get the part at `index`.
+    ///
+    /// This walks the buffer from the start unless the buffer is indexed.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.as_buffer_ref().get(index)
+    }
+}
+
+unsafe fn map(path: &Path) -> io::Result<Mmap> {
+    Mmap::map(&File::open(path)?)
+}
+
+impl AsRef<[u8]> for MmapBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+impl<'a> IntoIterator for &'a MmapBuffer {
+    type Item = &'a [u8];
+    type IntoIter = BufferIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::testing::{formats, parts, split_buffer_error};
+    use crate::{Buffer, LengthEncoding};
+
+    fn file(bytes: &[u8]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        file
+    }
+
+    #[test]
+    fn open_matches_buffer() {
+        for format in formats() {
+            let buffer = Buffer::build_with_format(parts(), format);
+            let file = file(buffer.as_bytes());
+            let mmap = unsafe { MmapBuffer::open_with_format(file.path(), format) }.unwrap();
+            assert_eq!(mmap.format(), format);
+            assert_eq!(mmap.len(), parts().len());
+            assert_eq!(mmap.is_indexed(), format.is_indexed());
+            assert!(mmap.iter().eq(buffer.iter()), "{:?}", format);
+            assert_eq!(mmap.get(4), buffer.get(4));
+            assert_eq!(mmap.last(), buffer.last());
+            assert_eq!(mmap.as_bytes(), buffer.as_bytes());
+
+            if format.has_header() || format == Format::default() {
+                let mmap = unsafe { MmapBuffer::open(file.path()) }.unwrap();
+                assert_eq!(mmap.format(), format);
+                assert!(mmap.iter().eq(buffer.iter()));
+            }
+        }
+    }
+
+    #[test]
+    fn open_empty_file() {
+        let file = file(&[]);
+        let mmap = unsafe { MmapBuffer::open(file.path()) }.unwrap();
+        assert_eq!(mmap.format(), Format::default());
+        assert!(mmap.is_empty());
+        assert_eq!(mmap.iter().next(), None);
+
+        let format = Format::new(LengthEncoding::Varint).with_header(true);
+        let err = unsafe { MmapBuffer::open_with_format(file.path(), format) }.unwrap_err();
+        assert_eq!(split_buffer_error(&err), &SplitBufferError::InvalidHeader);
+    }
+
+    #[test]
+    fn open_rejects_bad_files() {
+        let format = Format::new(LengthEncoding::U32).with_checksums(true).with_header(true);
+        let mut bytes = Buffer::build_with_format(parts(), format).into_inner();
+        let file = file(&bytes);
+        let expected = format.with_checksums(false);
+        let err = unsafe { MmapBuffer::open_with_format(file.path(), expected) }.unwrap_err();
+        assert_eq!(
+            split_buffer_error(&err),
+            &SplitBufferError::FormatMismatch { expected, found: format }
+        );
+
+        bytes[8 + 4] ^= 1;
+        let file = self::file(&bytes);
+        let err = unsafe { MmapBuffer::open(file.path()) }.unwrap_err();
+        let checksum = SplitBufferError::ChecksumMismatch { index: 0, offset: 8 };
+        assert_eq!(split_buffer_error(&err), &checksum);
+        let mmap = unsafe { MmapBuffer::open_unchecked(file.path()) }.unwrap();
+        assert_eq!(mmap.len(), parts().len());
+        assert_eq!(mmap.validate(), Err(checksum));
+
+        bytes[4] = 2;
+        let file = self::file(&bytes);
+        let err = unsafe { MmapBuffer::open(file.path()) }.unwrap_err();
+        assert_eq!(split_buffer_error(&err), &SplitBufferError::UnsupportedVersion { version: 2 });
+    }
+
+    #[test]
+    fn open_headerless_file_starting_with_magic() {
+        // A headerless buffer whose first part is 0x4655_4253 bytes long starts with "SBUF".
+        let len = u64::from_le_bytes(*b"SBUF\0\0\0\0");
+        let file = file(&len.to_le_bytes());
+        file.as_file().set_len(8 + len).unwrap();
+        let mmap = unsafe { MmapBuffer::open(file.path()) }.unwrap();
+        assert_eq!(mmap.format(), Format::default());
+        assert_eq!(mmap.len(), 1);
+        assert_eq!(mmap.get(0).map(<[u8]>::len), Some(len as usize));
+    }
+}
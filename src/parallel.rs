@@ -0,0 +1,276 @@
+use std::borrow::Cow;
+use std::slice;
+use std::vec::Vec;
+
+use rayon::iter::plumbing::{bridge, Consumer, Producer, ProducerCallback, UnindexedConsumer};
+use rayon::iter::{
+    FromParallelIterator, IndexedParallelIterator, IntoParallelIterator, ParallelExtend,
+    ParallelIterator,
+};
+
+use crate::{Buffer, BufferRef, Format};
+
+impl<'a> BufferRef<'a> {
+    /// Iterate over the parts of the buffer in parallel.
+    ///
+    /// This borrows the index of part offsets if there is one, or builds it first otherwise.
+    pub fn par_iter(&self) -> ParIter<'a> {
+        let offsets = match self.index() {
+            Some(offsets) => Cow::Borrowed(offsets),
+            None => Cow::Owned(self.offsets()),
+        };
+        ParIter { data: self.as_bytes(), format: self.format(), offsets }
+    }
+}
+
+impl Buffer {
+    /// Iterate over the parts of the buffer in parallel.
+    ///
+    /// This builds a temporary index of part offsets first unless the buffer is indexed.
+    pub fn par_iter(&self) -> ParIter<'_> {
+        self.as_buffer_ref().par_iter()
+    }
+
+    /// Build a buffer from a parallel iterator of parts using the given `Format`.
+    ///
+    /// Parts are encoded into chunks in parallel and concatenated in their original order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format uses `LengthEncoding::U32` and a part is longer than `u32::MAX` bytes.
+    pub fn par_build_with_format<I, T, F>(parts: I, format: F) -> Self
+    where
+        I: IntoParallelIterator<Item = T>,
+        T: AsRef<[u8]>,
+        F: Into<Format>,
+    {
+        let format = format.into();
+        let chunks: Vec<(Vec<u8>, usize)> = parts
+            .into_par_iter()
+            .fold(
+                || (Vec::new(), 0),
+                |(mut data, len), part| {
+                    format.write_part(&mut data, part.as_ref());
+                    (data, len + 1)
+                },
+            )
+            .collect();
+
+        let size = chunks.iter().map(|(data, _)| data.len()).sum::<usize>();
+        let mut data = Vec::with_capacity(format.header_len() + size);
+        format.write_header(&mut data);
+        let mut len = 0;
+        for (chunk, chunk_len) in chunks {
+            data.extend_from_slice(&chunk);
+            len += chunk_len;
+        }
+        Buffer::from_raw_parts(data, format, len)
+    }
+}
+
+impl<T: AsRef<[u8]> + Send> FromParallelIterator<T> for Buffer {
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(parts: I) -> Self {
+        Self::par_build_with_format(parts, Format::default())
+    }
+}
+
+/// Appends parts in the buffer's own `Format`, keeping the index up to date if there is one.
+///
+/// # Panics
+///
+/// Panics if the format uses `LengthEncoding::U32` and a part is longer than `u32::MAX` bytes.
+impl<T: AsRef<[u8]> + Send> ParallelExtend<T> for Buffer {
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, parts: I) {
+        let mut other = Self::par_build_with_format(parts, self.format.with_header(false));
+        self.append(&mut other);
+    }
+}
+
+impl<'a> IntoParallelIterator for &'a Buffer {
+    type Iter = ParIter<'a>;
+    type Item = &'a [u8];
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+impl<'a> IntoParallelIterator for BufferRef<'a> {
+    type Iter = ParIter<'a>;
+    type Item = &'a [u8];
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+/// Parallel iterator over parts of a `Buffer`
+#[derive(Debug)]
+pub struct ParIter<'a> {
+    data: &'a [u8],
+    format: Format,
+    offsets: Cow<'a, [usize]>,
+}
+
+impl<'a> ParallelIterator for ParIter<'a> {
+    type Item = &'a [u8];
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.offsets.len())
+    }
+}
+
+impl<'a> IndexedParallelIterator for ParIter<'a> {
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        callback.callback(PartsProducer {
+            data: self.data,
+            format: self.format,
+            offsets: &self.offsets,
+        })
+    }
+}
+
+/// Splits a range of parts between rayon jobs
+struct PartsProducer<'o, 'a> {
+    data: &'a [u8],
+    format: Format,
+    offsets: &'o [usize],
+}
+
+impl<'o, 'a> Producer for PartsProducer<'o, 'a> {
+    type Item = &'a [u8];
+    type IntoIter = Parts<'o, 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Parts { data: self.data, format: self.format, offsets: self.offsets.iter() }
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (left, right) = self.offsets.split_at(index);
+        (PartsProducer { offsets: left, ..self }, PartsProducer { offsets: right, ..self })
+    }
+}
+
+/// Sequential iterator over the parts of one rayon job
+struct Parts<'o, 'a> {
+    data: &'a [u8],
+    format: Format,
+    offsets: slice::Iter<'o, usize>,
+}
+
+impl<'o, 'a> Parts<'o, 'a> {
+    fn part(&self, offset: usize) -> &'a [u8] {
+        let (range, _) =
+            self.format.read_part(self.data, offset).expect("Part offsets must be valid");
+        &self.data[range]
+    }
+}
+
+impl<'o, 'a> Iterator for Parts<'o, 'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = *self.offsets.next()?;
+        Some(self.part(offset))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.offsets.size_hint()
+    }
+}
+
+impl<'o, 'a> DoubleEndedIterator for Parts<'o, 'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let offset = *self.offsets.next_back()?;
+        Some(self.part(offset))
+    }
+}
+
+impl<'o, 'a> ExactSizeIterator for Parts<'o, 'a> {}
+
+#[cfg(test)]
+mod tests {
+    use rayon::prelude::*;
+
+    use super::*;
+    use crate::testing::{formats, parts};
+
+    /// Enough parts for rayon to split them between jobs
+    fn many_parts() -> Vec<Vec<u8>> {
+        (0..40).flat_map(|_| parts()).collect()
+    }
+
+    #[test]
+    fn par_iter_matches_iter() {
+        for format in formats() {
+            let mut buffer = Buffer::build_with_format(many_parts(), format);
+            for _ in 0..2 {
+                let parts: Vec<_> = buffer.par_iter().with_min_len(1).collect();
+                assert!(parts.iter().copied().eq(buffer.iter()), "{:?}", format);
+                let parts: Vec<_> = buffer.par_iter().with_min_len(1).rev().collect();
+                assert!(parts.iter().copied().eq(buffer.iter().rev()));
+                assert_eq!(buffer.par_iter().len(), buffer.len());
+                buffer.build_index();
+            }
+
+            let (front, back) = buffer.split_at(7);
+            let parts: Vec<_> = front.into_par_iter().with_min_len(1).collect();
+            assert!(parts.iter().copied().eq(front.iter()));
+            let parts: Vec<_> = back.into_par_iter().with_min_len(1).collect();
+            assert!(parts.iter().copied().eq(back.iter()));
+            assert_eq!(parts.len(), buffer.len() - 7);
+        }
+    }
+
+    #[test]
+    fn par_iter_empty() {
+        let buffer = Buffer::build(Vec::<Vec<u8>>::new());
+        assert_eq!(buffer.par_iter().count(), 0);
+    }
+
+    #[test]
+    fn par_build_matches_build() {
+        let parts = many_parts();
+        let buffer: Buffer = parts.par_iter().with_min_len(1).collect();
+        assert_eq!(buffer.as_bytes(), Buffer::build(&parts).as_bytes());
+        assert_eq!(buffer.len(), parts.len());
+
+        for format in formats() {
+            let buffer = Buffer::par_build_with_format(parts.par_iter().with_min_len(1), format);
+            let expected = Buffer::build_with_format(&parts, format);
+            assert_eq!(buffer.as_bytes(), expected.as_bytes());
+            assert_eq!(buffer.len(), expected.len());
+            assert_eq!(buffer.format(), format);
+            assert_eq!(buffer.is_indexed(), format.is_indexed());
+            assert_eq!(buffer.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn par_extend_matches_extend() {
+        let parts = many_parts();
+        for format in formats().into_iter().filter(Format::has_header) {
+            let mut buffer = Buffer::build_with_format([b"first"], format);
+            let mut expected = buffer.clone();
+            buffer.par_extend(parts.par_iter().with_min_len(1));
+            expected.extend(&parts);
+            assert_eq!(buffer.as_bytes(), expected.as_bytes(), "{:?}", format);
+            assert_eq!(buffer.len(), parts.len() + 1);
+            assert_eq!(buffer.is_indexed(), format.is_indexed());
+            assert_eq!(buffer.get(parts.len()), parts.last().map(Vec::as_slice));
+            assert_eq!(buffer.validate(), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::io::{self, Read};
+use std::mem;
+use std::vec::Vec;
+
+use crate::crc::crc32c;
+use crate::format::{CHECKSUM_LEN, HEADER_LEN};
+use crate::{Format, SplitBufferError};
+
+/// Reads parts incrementally from an `io::Read` encoded in the same framing as `Buffer`
+///
+/// Length prefixes are read in small pieces, so wrap unbuffered readers such as files and sockets
+/// in an `io::BufReader`. Memory for a part is allocated as its bytes arrive rather than up front,
+/// but a limit can also be set with `with_max_part_len` to reject hostile length prefixes early.
+///
+/// Checksums are always verified. Malformed framing is reported as an `io::Error` of kind
+/// `InvalidData` wrapping a `SplitBufferError`, and a stream that ends within a part as
+/// `UnexpectedEof`.
+#[derive(Debug)]
+pub struct BufferReader<R: Read> {
+    inner: R,
+    format: Format,
+    max_part_len: Option<usize>,
+    pending: Vec<u8>,
+    consumed: usize,
+    offset: usize,
+    position: usize,
+    part: Vec<u8>,
+    header_error: Option<SplitBufferError>,
+    done: bool,
+}
+
+impl<R: Read> BufferReader<R> {
+    /// Create a reader, using the `Format` recorded in the header or the default `Format` if the
+    /// stream has no header.
+    ///
+    /// A headerless stream can start with the magic bytes of a header if its first part is long
+    /// enough, so a stream with an unreadable header is read without one. If the first part
+    /// cannot be read either, the header error is returned in its place.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        (&mut inner).take(HEADER_LEN as u64).read_to_end(&mut header)?;
+        let (format, header_error) = match Format::detect(&header) {
+            Ok(format) => (format, None),
+            Err(e) => (Format::default(), Some(e)),
+        };
+        let mut reader = Self::from_raw_parts(inner, format);
+        if format.has_header() {
+            reader.offset = HEADER_LEN;
+        } else {
+            reader.pending = header;
+            reader.header_error = header_error;
+        }
+        Ok(reader)
+    }
+
+    /// Create a reader for a stream encoded with the given `Format`.
+    ///
+    /// If the format has a header, the header in the stream must match it.
+    pub fn with_format<F: Into<Format>>(inner: R, format: F) -> io::Result<Self> {
+        let format = format.into();
+        let mut reader = Self::from_raw_parts(inner, format);
+        if format.has_header() {
+            let mut header = [0; HEADER_LEN];
+            reader.read_exact(&mut header)?;
+            format.check_header(&header)?;
+        }
+        Ok(reader)
+    }
+
+    fn from_raw_parts(inner: R, format: Format) -> Self {
+        BufferReader {
+            inner,
+            format,
+            max_part_len: None,
+            pending: Vec::new(),
+            consumed: 0,
+            offset: 0,
+            position: 0,
+            part: Vec::new(),
+            header_error: None,
+            done: false,
+        }
+    }
+
+    /// Reject parts longer than `max` bytes with `SplitBufferError::PartTooLong`.
+    pub fn with_max_part_len(mut self, max: usize) -> Self {
+        self.max_part_len = Some(max);
+        self
+    }
+
+    /// Get the `Format` parts are read in
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Read the next part into `part`, replacing its contents.
+    ///
+    /// Returns `false` if the stream ended cleanly before another part.
+    pub fn read_part(&mut self, part: &mut Vec<u8>) -> io::Result<bool> {
+        let result = self.read_next(part);
+        match (result, self.header_error.take()) {
+            (Err(e), Some(header_error)) if is_framing_error(&e) => Err(header_error.into()),
+            (result, _) => result,
+        }
+    }
+
+    fn read_next(&mut self, part: &mut Vec<u8>) -> io::Result<bool> {
+        part.clear();
+        let offset = self.offset;
+        let lengths = self.format.lengths();
+
+        let mut prefix = [0; 10];
+        let mut read = 0;
+        loop {
+            if read == 0 {
+                if self.read_some(&mut prefix[..1])? == 0 {
+                    return Ok(false);
+                }
+            } else {
+                self.read_exact(&mut prefix[read..read + 1])?;
+            }
+            read += 1;
+            let done = match lengths.width() {
+                Some(width) => read == width,
+                None => prefix[read - 1] & 0x80 == 0 || read == prefix.len(),
+            };
+            if done {
+                break;
+            }
+        }
+        let (len, read) = lengths.read(&prefix[..read], offset)?;
+
+        if let Some(max) = self.max_part_len.filter(|max| len > *max) {
+            return Err(SplitBufferError::PartTooLong { offset, len, max }.into());
+        }
+        self.read_to_vec(part, len)?;
+
+        if self.format.has_checksums() {
+            let mut checksum = [0; CHECKSUM_LEN];
+            self.read_exact(&mut checksum)?;
+            if crc32c(part).to_le_bytes() != checksum {
+                let index = self.position;
+                return Err(SplitBufferError::ChecksumMismatch { index, offset }.into());
+            }
+        }
+
+        if self.format.has_trailing_lengths() {
+            let mut trailer = [0; 10];
+            self.read_exact(&mut trailer[..read])?;
+            match lengths.read_trailer(&trailer[..read], self.offset - read)? {
+                (trailing, trailer_read) if trailing == len && trailer_read == read => {}
+                _ => return Err(SplitBufferError::LengthMismatch { offset }.into()),
+            }
+        }
+
+        self.position += 1;
+        Ok(true)
+    }
+
+    /// Read the next part into a buffer owned by the reader.
+    ///
+    /// The returned slice is only valid until the next call; use `read_part` to keep parts.
+    pub fn next_part(&mut self) -> io::Result<Option<&[u8]>> {
+        let mut part = mem::take(&mut self.part);
+        let result = self.read_part(&mut part);
+        self.part = part;
+        match result? {
+            true => Ok(Some(&self.part)),
+            false => Ok(None),
+        }
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    ///
+    /// Reading from it directly will corrupt the framing.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Get the underlying reader.
+    ///
+    /// Bytes read ahead while detecting the header are lost if no part has been read yet.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read `len` bytes onto the end of `vec`, growing it as the bytes arrive.
+    fn read_to_vec(&mut self, vec: &mut Vec<u8>, len: usize) -> io::Result<()> {
+        const CHUNK: usize = 64 * 1024;
+        let mut remaining = len;
+        while remaining > 0 {
+            let start = vec.len();
+            let chunk = remaining.min(CHUNK);
+            vec.resize(start + chunk, 0);
+            self.read_exact(&mut vec[start..])?;
+            remaining -= chunk;
+        }
+        Ok(())
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_some(buf)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                read => buf = &mut buf[read..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Read into `buf`, draining bytes read ahead while detecting the header first.
+    fn read_some(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = if self.consumed < self.pending.len() {
+            let read = (&self.pending[self.consumed..]).read(buf)?;
+            self.consumed += read;
+            read
+        } else {
+            loop {
+                match self.inner.read(buf) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            }
+        };
+        self.offset += read;
+        Ok(read)
+    }
+}
+
+/// Check whether `e` means the stream is not framed as expected, rather than that it could not be
+/// read.
+pub(crate) fn is_framing_error(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof)
+}
+
+/// Iterating stops after the first error.
+impl<R: Read> Iterator for BufferReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut part = Vec::new();
+        match self.read_part(&mut part) {
+            Ok(true) => Some(Ok(part)),
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+    use crate::testing::{formats, parts, split_buffer_error};
+    use crate::{Buffer, BufferWriter, LengthEncoding};
+
+    /// Reader returning one byte per call, to exercise every partial read.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn read_matches_build() {
+        for format in formats() {
+            let buffer = Buffer::build_with_format(parts(), format);
+            let reader = BufferReader::with_format(Trickle(buffer.as_bytes()), format).unwrap();
+            assert_eq!(reader.format(), format);
+            assert_eq!(reader.collect::<io::Result<Vec<_>>>().unwrap(), parts());
+
+            let mut writer = BufferWriter::with_format(Vec::new(), format);
+            for part in parts() {
+                writer.write_part(&part).unwrap();
+            }
+            let (bytes, _) = writer.finish().unwrap();
+            if format.has_header() || format == Format::default() {
+                let reader = BufferReader::new(Trickle(&bytes)).unwrap();
+                assert_eq!(reader.format(), format);
+                assert_eq!(reader.collect::<io::Result<Vec<_>>>().unwrap(), parts());
+            }
+        }
+    }
+
+    #[test]
+    fn new_reads_ahead_headerless_streams() {
+        // Shorter than a header, so the whole stream is read ahead.
+        let buffer = Buffer::build([b"a"]);
+        let mut reader = BufferReader::new(Trickle(&buffer.as_bytes()[..8])).unwrap();
+        assert_eq!(reader.format(), Format::default());
+        assert_eq!(reader.next_part().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let buffer = Buffer::build(parts());
+        let mut reader = BufferReader::new(buffer.as_bytes()).unwrap();
+        assert_eq!(reader.format(), Format::default());
+        for part in parts() {
+            assert_eq!(reader.next_part().unwrap(), Some(&part[..]));
+        }
+        assert_eq!(reader.next_part().unwrap(), None);
+
+        let reader = BufferReader::new(&[][..]).unwrap();
+        assert_eq!(reader.count(), 0);
+    }
+
+    #[test]
+    fn new_reports_unsupported_headers() {
+        let format = Format::default().with_header(true);
+        let mut bytes = Buffer::build_with_format(parts(), format).into_inner();
+        bytes[4] = 2;
+        let mut reader = BufferReader::new(&bytes[..]).unwrap();
+        assert_eq!(
+            *split_buffer_error(&reader.next().unwrap().unwrap_err()),
+            SplitBufferError::UnsupportedVersion { version: 2 }
+        );
+        assert!(reader.next().is_none());
+
+        bytes[4] = 1;
+        bytes[7] = 0x80;
+        let mut reader = BufferReader::new(&bytes[..]).unwrap();
+        assert_eq!(
+            *split_buffer_error(&reader.next().unwrap().unwrap_err()),
+            SplitBufferError::UnsupportedFlags { flags: 0x8000 }
+        );
+
+        let err = BufferReader::with_format(&bytes[..], format).unwrap_err();
+        assert_eq!(*split_buffer_error(&err), SplitBufferError::UnsupportedFlags { flags: 0x8000 });
+        let err = BufferReader::with_format(&bytes[..4], format).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn max_part_len() {
+        let buffer = Buffer::build(parts());
+        let mut reader = BufferReader::new(buffer.as_bytes()).unwrap().with_max_part_len(299);
+        for part in &parts()[..4] {
+            assert_eq!(&reader.next().unwrap().unwrap(), part);
+        }
+        assert_eq!(
+            *split_buffer_error(&reader.next().unwrap().unwrap_err()),
+            SplitBufferError::PartTooLong { offset: 290, len: 300, max: 299 }
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn truncated_stream() {
+        for format in formats() {
+            let bytes = Buffer::build_with_format(parts(), format).into_inner();
+            let prefix = format.lengths().encoded_len(3);
+            let first = format.header_len() + format.encoded_len(3);
+            // Cut within the first prefix, within the first part and within the last part.
+            for &len in &[format.header_len() + prefix - 1, first - 1, bytes.len() - 1] {
+                if len < format.header_len() + 1 {
+                    continue;
+                }
+                let mut reader = BufferReader::with_format(Trickle(&bytes[..len]), format).unwrap();
+                let err = reader.find_map(Result::err).unwrap();
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{:?} at {}", format, len);
+            }
+            let reader = BufferReader::with_format(&bytes[..first], format).unwrap();
+            assert_eq!(reader.collect::<io::Result<Vec<_>>>().unwrap(), vec![b"abc".to_vec()]);
+        }
+    }
+
+    #[test]
+    fn corrupted_stream() {
+        let format = Format::new(LengthEncoding::U32).with_checksums(true);
+        let mut bytes = Buffer::build_with_format(parts(), format).into_inner();
+        bytes[4] ^= 1;
+        let mut reader = BufferReader::with_format(&bytes[..], format).unwrap();
+        assert_eq!(
+            *split_buffer_error(&reader.read_part(&mut Vec::new()).unwrap_err()),
+            SplitBufferError::ChecksumMismatch { index: 0, offset: 0 }
+        );
+
+        let format = Format::new(LengthEncoding::Varint).with_trailing_lengths(true);
+        let mut bytes = Buffer::build_with_format(parts(), format).into_inner();
+        bytes[4] = 2;
+        let mut reader = BufferReader::with_format(&bytes[..], format).unwrap();
+        assert_eq!(
+            *split_buffer_error(&reader.read_part(&mut Vec::new()).unwrap_err()),
+            SplitBufferError::LengthMismatch { offset: 0 }
+        );
+
+        let mut bytes = Buffer::build_with_format(parts(), LengthEncoding::Varint).into_inner();
+        bytes[4] = 0x80;
+        bytes[5] = 0x00;
+        let mut reader = BufferReader::with_format(&bytes[..], LengthEncoding::Varint).unwrap();
+        assert_eq!(reader.next_part().unwrap(), Some(&b"abc"[..]));
+        assert_eq!(
+            *split_buffer_error(&reader.next_part().unwrap_err()),
+            SplitBufferError::InvalidLength { offset: 4 }
+        );
+    }
+
+    #[test]
+    fn next_part_reuses_its_buffer() {
+        let buffer = Buffer::build([&[1; 300][..], b"abc", b""]);
+        let mut reader = BufferReader::new(buffer.as_bytes()).unwrap();
+        let large = reader.next_part().unwrap().unwrap().as_ptr();
+        assert_eq!(reader.next_part().unwrap().unwrap().as_ptr(), large);
+        assert_eq!(reader.next_part().unwrap(), Some(&b""[..]));
+        assert!(reader.part.capacity() >= 300);
+        assert_eq!(reader.next_part().unwrap(), None);
+    }
+}
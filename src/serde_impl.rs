@@ -0,0 +1,297 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
+
+use crate::{Buffer, BufferBuilder, Format, LengthEncoding};
+
+/// Binary formats store the encoded bytes as a single byte string, including the header if the
+/// buffer has one. Buffers in a `Format` other than the default are always stored with a header,
+/// so that the bytes can be decoded with `Buffer::from_vec`.
+///
+/// Human-readable formats store a sequence of byte strings, one per part. Buffers in a `Format`
+/// other than the default are stored as a map with a `format` and the `parts`.
+impl Serialize for Buffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            if self.format == Format::default() {
+                Parts(self).serialize(serializer)
+            } else {
+                let mut map = serializer.serialize_struct("Buffer", 2)?;
+                map.serialize_field("format", &FormatDef(self.format))?;
+                map.serialize_field("parts", &Parts(self))?;
+                map.end()
+            }
+        } else if self.format.has_header() || self.format == Format::default() {
+            serializer.serialize_bytes(self.as_bytes())
+        } else {
+            let format = self.format.with_header(true);
+            let mut bytes = Vec::with_capacity(format.header_len() + self.as_bytes().len());
+            format.write_header(&mut bytes);
+            bytes.extend_from_slice(self.as_bytes());
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+}
+
+/// Encoded bytes are validated with `Buffer::from_vec`, so a buffer that was serialized without
+/// a header in a `Format` other than the default is deserialized with one.
+impl<'de> Deserialize<'de> for Buffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(BufferVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(BufferVisitor)
+        }
+    }
+}
+
+struct Parts<'a>(&'a Buffer);
+
+impl Serialize for Parts<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for part in self.0 {
+            seq.serialize_element(&Part(part))?;
+        }
+        seq.end()
+    }
+}
+
+struct Part<'a>(&'a [u8]);
+
+impl Serialize for Part<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct BufferVisitor;
+
+impl<'de> Visitor<'de> for BufferVisitor {
+    type Value = Buffer;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an encoded buffer, a sequence of byte strings or a map of parts")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Buffer, E> {
+        self.visit_byte_buf(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Buffer, E> {
+        Buffer::from_vec(bytes).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Buffer, A::Error> {
+        let mut builder = BufferBuilder::new();
+        while let Some(PartBuf(part)) = seq.next_element()? {
+            builder.push(&part);
+        }
+        Ok(builder.finish())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Buffer, A::Error> {
+        let mut format = None;
+        let mut parts = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "format" => format = Some(map.next_value::<FormatDef>()?.0),
+                "parts" => parts = Some(map.next_value::<Vec<PartBuf>>()?),
+                _ => return Err(de::Error::unknown_field(&key, &["format", "parts"])),
+            }
+        }
+        let format = format.ok_or_else(|| de::Error::missing_field("format"))?;
+        let parts = parts.ok_or_else(|| de::Error::missing_field("parts"))?;
+        let mut builder = BufferBuilder::with_format(format);
+        builder.extend(parts.iter().map(|PartBuf(part)| part));
+        Ok(builder.finish())
+    }
+}
+
+/// Human-readable representation of a `Format`
+struct FormatDef(Format);
+
+const FORMAT_FIELDS: &[&str] = &["lengths", "trailing_lengths", "checksums", "indexed", "header"];
+
+impl Serialize for FormatDef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let lengths = match self.0.lengths() {
+            LengthEncoding::U32 => "U32",
+            LengthEncoding::U64 => "U64",
+            LengthEncoding::Varint => "Varint",
+        };
+        let mut map = serializer.serialize_struct("Format", FORMAT_FIELDS.len())?;
+        map.serialize_field("lengths", lengths)?;
+        map.serialize_field("trailing_lengths", &self.0.has_trailing_lengths())?;
+        map.serialize_field("checksums", &self.0.has_checksums())?;
+        map.serialize_field("indexed", &self.0.is_indexed())?;
+        map.serialize_field("header", &self.0.has_header())?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for FormatDef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Format", FORMAT_FIELDS, FormatVisitor)
+    }
+}
+
+struct FormatVisitor;
+
+impl<'de> Visitor<'de> for FormatVisitor {
+    type Value = FormatDef;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a buffer format")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FormatDef, A::Error> {
+        let mut lengths = None;
+        let (mut trailing_lengths, mut checksums, mut indexed, mut header) =
+            (false, false, false, false);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "lengths" => {
+                    lengths = Some(match map.next_value::<String>()?.as_str() {
+                        "U32" => LengthEncoding::U32,
+                        "U64" => LengthEncoding::U64,
+                        "Varint" => LengthEncoding::Varint,
+                        other => {
+                            return Err(de::Error::unknown_variant(
+                                other,
+                                &["U32", "U64", "Varint"],
+                            ))
+                        }
+                    })
+                }
+                "trailing_lengths" => trailing_lengths = map.next_value()?,
+                "checksums" => checksums = map.next_value()?,
+                "indexed" => indexed = map.next_value()?,
+                "header" => header = map.next_value()?,
+                _ => return Err(de::Error::unknown_field(&key, FORMAT_FIELDS)),
+            }
+        }
+        let lengths = lengths.ok_or_else(|| de::Error::missing_field("lengths"))?;
+        Ok(FormatDef(
+            Format::new(lengths)
+                .with_trailing_lengths(trailing_lengths)
+                .with_checksums(checksums)
+                .with_index(indexed)
+                .with_header(header),
+        ))
+    }
+}
+
+struct PartBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for PartBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_byte_buf(PartVisitor)
+    }
+}
+
+struct PartVisitor;
+
+impl<'de> Visitor<'de> for PartVisitor {
+    type Value = PartBuf;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte string")
+    }
+
+    fn visit_str<E: de::Error>(self, part: &str) -> Result<PartBuf, E> {
+        self.visit_bytes(part.as_bytes())
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<PartBuf, E> {
+        Ok(PartBuf(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<PartBuf, E> {
+        Ok(PartBuf(bytes))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<PartBuf, A::Error> {
+        let mut part = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element()? {
+            part.push(byte);
+        }
+        Ok(PartBuf(part))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{formats, parts};
+
+    #[test]
+    fn binary_round_trip() {
+        for format in formats() {
+            let buffer = Buffer::build_with_format(parts(), format);
+            let bytes = bincode::serialize(&buffer).unwrap();
+            let decoded: Buffer = bincode::deserialize(&bytes).unwrap();
+            assert!(decoded.iter().eq(buffer.iter()), "{:?}", format);
+            assert_eq!(decoded.is_indexed(), format.is_indexed());
+            if format == Format::default() || format.has_header() {
+                assert_eq!(decoded.format(), format);
+                assert_eq!(decoded.as_bytes(), buffer.as_bytes());
+            } else {
+                assert_eq!(decoded.format(), format.with_header(true));
+                assert_eq!(&decoded.as_bytes()[8..], buffer.as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn human_readable_round_trip() {
+        for format in formats() {
+            let buffer = Buffer::build_with_format(parts(), format);
+            let json = serde_json::to_string(&buffer).unwrap();
+            let decoded: Buffer = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded.format(), format);
+            assert_eq!(decoded.is_indexed(), format.is_indexed());
+            assert_eq!(decoded.as_bytes(), buffer.as_bytes());
+        }
+    }
+
+    #[test]
+    fn human_readable_layout() {
+        let buffer = Buffer::build([&b"ab"[..], b"c"]);
+        assert_eq!(serde_json::to_string(&buffer).unwrap(), "[[97,98],[99]]");
+        let decoded: Buffer = serde_json::from_str(r#"["ab", [99]]"#).unwrap();
+        assert_eq!(decoded.as_bytes(), buffer.as_bytes());
+
+        let format = Format::new(LengthEncoding::Varint).with_checksums(true);
+        let buffer = Buffer::build_with_format([b"ab"], format);
+        assert_eq!(
+            serde_json::to_string(&buffer).unwrap(),
+            r#"{"format":{"lengths":"Varint","trailing_lengths":false,"checksums":true,"indexed":false,"header":false},"parts":[[97,98]]}"#
+        );
+        let decoded: Buffer = serde_json::from_str(
+            r#"{"parts":["ab"],"format":{"lengths":"Varint","checksums":true}}"#,
+        )
+        .unwrap();
+        assert_eq!(decoded.format(), format);
+        assert_eq!(decoded.as_bytes(), buffer.as_bytes());
+
+        assert!(serde_json::from_str::<Buffer>(r#"{"parts":[]}"#).is_err());
+        assert!(
+            serde_json::from_str::<Buffer>(r#"{"format":{"lengths":"U16"},"parts":[]}"#).is_err()
+        );
+        assert!(serde_json::from_str::<Buffer>(
+            r#"{"format":{"lengths":"U32","extra":1},"parts":[]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn binary_rejects_bad_framing() {
+        let mut bytes = bincode::serialize(&Buffer::build([b"abc"])).unwrap();
+        bytes.pop();
+        assert!(bincode::deserialize::<Buffer>(&bytes).is_err());
+    }
+}
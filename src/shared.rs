@@ -0,0 +1,246 @@
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+
+use bytes::Bytes;
+
+use crate::{Buffer, BufferIterator, BufferRef, Format, SplitBufferError};
+
+/// Buffer backed by reference counted `Bytes`
+///
+/// Parts are returned as `Bytes` handles into the same allocation, so they can be sent to other
+/// tasks without copying. Cloning a `SharedBuffer` is cheap.
+#[derive(Clone, Debug)]
+pub struct SharedBuffer {
+    data: Bytes,
+    format: Format,
+    len: usize,
+    index: Option<Arc<[usize]>>,
+}
+
+impl SharedBuffer {
+    /// Create a buffer from encoded bytes, validating the framing.
+    ///
+    /// The `Format` recorded in the header is used, or the default `Format` if there is no
+    /// header.
+    ///
+    /// See `BufferRef::from_slice` for how bytes with an unreadable header are handled.
+    pub fn from_bytes(bytes: Bytes) -> Result<Self, SplitBufferError> {
+        Ok(Self::from_validated(&bytes, BufferRef::from_slice(&bytes)?))
+    }
+
+    /// Create a buffer from bytes encoded with the given `Format`, validating the framing.
+    ///
+    /// If the format has a header, the header in `bytes` must match it.
+    pub fn from_bytes_with_format<F: Into<Format>>(
+        bytes: Bytes,
+        format: F,
+    ) -> Result<Self, SplitBufferError> {
+        Ok(Self::from_validated(&bytes, BufferRef::from_slice_with_format(&bytes, format)?))
+    }
+
+    /// Share `bytes` once they have been validated as `view`.
+    fn from_validated(bytes: &Bytes, view: BufferRef<'_>) -> Self {
+        let format = view.format();
+        let index = if format.is_indexed() { Some(view.offsets().into()) } else { None };
+        SharedBuffer { data: bytes.clone(), format, len: view.len(), index }
+    }
+
+    /// Get the `Format` used to encode the buffer
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get a borrowed `BufferRef` view of the buffer.
+    pub fn as_buffer_ref(&self) -> BufferRef<'_> {
+        BufferRef::from_raw_parts(&self.data, self.format, self.len, self.index.as_deref())
+    }
+
+    /// Get the encoded bytes
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// Get the number of parts.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the buffer has no parts.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the parts of the buffer as `Bytes` handles.
+    pub fn iter(&self) -> SharedBufferIterator<'_> {
+        SharedBufferIterator { data: &self.data, inner: self.as_buffer_ref().iter() }
+    }
+
+    /// Get the part at `index`.
+    ///
+    /// This walks the buffer from the start unless the buffer is indexed.
+    pub fn get(&self, index: usize) -> Option<Bytes> {
+        let (range, _) = self.as_buffer_ref().span(index)?;
+        Some(self.data.slice(range))
+    }
+
+    /// Get the inner `Bytes`
+    pub fn into_bytes(self) -> Bytes {
+        self.data
+    }
+}
+
+impl From<Buffer> for SharedBuffer {
+    fn from(buffer: Buffer) -> Self {
+        let Buffer { data, format, len, index } = buffer;
+        SharedBuffer { data: data.into(), format, len, index: index.map(Into::into) }
+    }
+}
+
+impl From<Buffer> for Bytes {
+    fn from(buffer: Buffer) -> Self {
+        buffer.into_inner().into()
+    }
+}
+
+impl From<SharedBuffer> for Bytes {
+    fn from(buffer: SharedBuffer) -> Self {
+        buffer.into_bytes()
+    }
+}
+
+impl TryFrom<Bytes> for SharedBuffer {
+    type Error = SplitBufferError;
+
+    fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
+        SharedBuffer::from_bytes(bytes)
+    }
+}
+
+impl AsRef<[u8]> for SharedBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl<'a> IntoIterator for &'a SharedBuffer {
+    type Item = Bytes;
+    type IntoIter = SharedBufferIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over parts of a `SharedBuffer`
+pub struct SharedBufferIterator<'a> {
+    data: &'a Bytes,
+    inner: BufferIterator<'a>,
+}
+
+impl<'a> Iterator for SharedBufferIterator<'a> {
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|part| self.data.slice_ref(part))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for SharedBufferIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|part| self.data.slice_ref(part))
+    }
+}
+
+impl<'a> ExactSizeIterator for SharedBufferIterator<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::LengthEncoding;
+
+    fn parts() -> Vec<Vec<u8>> {
+        vec![b"abc".to_vec(), Vec::new(), vec![7; 300], b"de".to_vec()]
+    }
+
+    #[test]
+    fn parts_share_the_allocation() {
+        let format = Format::new(LengthEncoding::Varint).with_checksums(true).with_header(true);
+        let shared =
+            SharedBuffer::try_from(Bytes::from(Buffer::build_with_format(parts(), format)))
+                .unwrap();
+        assert_eq!(shared.format(), format);
+        assert_eq!(shared.len(), 4);
+        let range = shared.as_bytes().as_ptr_range();
+        for (part, expected) in shared.iter().zip(parts()) {
+            assert_eq!(part, expected);
+            assert!(range.contains(&part.as_ptr()) || part.is_empty());
+        }
+        let part = shared.get(2).unwrap();
+        assert!(range.contains(&part.as_ptr()));
+        assert_eq!(shared.get(4), None);
+    }
+
+    #[test]
+    fn reverse_iteration() {
+        for &trailing in &[false, true] {
+            let format = Format::new(LengthEncoding::U32).with_trailing_lengths(trailing);
+            let shared = SharedBuffer::from(Buffer::build_with_format(parts(), format));
+            assert!(shared.iter().rev().eq(parts().into_iter().rev()));
+            let mut iter = shared.iter();
+            assert_eq!(iter.next_back().unwrap(), &b"de"[..]);
+            assert_eq!(iter.len(), 3);
+            assert_eq!(iter.next().unwrap(), &b"abc"[..]);
+        }
+    }
+
+    #[test]
+    fn from_buffer_keeps_format_and_index() {
+        let format = Format::new(LengthEncoding::U64).with_trailing_lengths(true);
+        let mut buffer = Buffer::build_with_format(parts(), format);
+        buffer.build_index();
+        let bytes = buffer.as_bytes().to_vec();
+        let shared = SharedBuffer::from(buffer);
+        assert_eq!(shared.format(), format);
+        assert!(shared.index.is_some());
+        assert_eq!(shared.as_buffer_ref().get(3), Some(&b"de"[..]));
+        assert_eq!(Bytes::from(shared), bytes);
+
+        let format = format.with_index(true).with_header(true);
+        let shared =
+            SharedBuffer::from_bytes(Buffer::build_with_format(parts(), format).into()).unwrap();
+        assert_eq!(shared.format(), format);
+        assert_eq!(shared.index.as_deref(), Some(&[8, 27, 43, 359][..]));
+    }
+
+    #[test]
+    fn rejects_bad_framing() {
+        let mut bytes = Buffer::build(parts()).into_inner();
+        bytes.pop();
+        assert_eq!(
+            SharedBuffer::try_from(Bytes::from(bytes)).unwrap_err(),
+            SplitBufferError::PartOutOfBounds { offset: 327, len: 2, available: 1 }
+        );
+
+        let format = Format::new(LengthEncoding::U32).with_checksums(true);
+        let mut bytes = Buffer::build_with_format(parts(), format).into_inner();
+        bytes[5] ^= 1;
+        assert_eq!(
+            SharedBuffer::from_bytes_with_format(bytes.into(), format).unwrap_err(),
+            SplitBufferError::ChecksumMismatch { index: 0, offset: 0 }
+        );
+
+        let mut bytes = Buffer::build_with_format(parts(), format.with_header(true)).into_inner();
+        bytes[4] = 2;
+        assert_eq!(
+            SharedBuffer::from_bytes(bytes.into()).unwrap_err(),
+            SplitBufferError::UnsupportedVersion { version: 2 }
+        );
+    }
+}
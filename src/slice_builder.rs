@@ -0,0 +1,204 @@
+use crate::{BufferRef, CapacityError, Format, LengthEncoding};
+
+/// Builder that encodes parts into a caller-provided slice without allocating
+///
+/// Arrays can be used as storage too, e.g. `SliceBuilder::new(&mut [0; 256])`. The finished
+/// `BufferRef` borrows the encoded bytes from the start of the slice.
+#[derive(Debug)]
+pub struct SliceBuilder<'a> {
+    data: &'a mut [u8],
+    format: Format,
+    written: usize,
+    len: usize,
+}
+
+impl<'a> SliceBuilder<'a> {
+    /// Create an empty builder using the default `Format`.
+    pub fn new(data: &'a mut [u8]) -> Self {
+        SliceBuilder { data, format: Format::default(), written: 0, len: 0 }
+    }
+
+    /// Create an empty builder using the given `Format`.
+    ///
+    /// Fails if the slice has no room for the header.
+    pub fn with_format<F: Into<Format>>(
+        data: &'a mut [u8],
+        format: F,
+    ) -> Result<Self, CapacityError> {
+        let format = format.into();
+        let mut builder = SliceBuilder { data, format, written: 0, len: 0 };
+        builder.write(&format.encode_header()[..format.header_len()])?;
+        Ok(builder)
+    }
+
+    /// Get the number of parts pushed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether no parts have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the number of bytes left in the slice.
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.written
+    }
+
+    /// Append a part.
+    ///
+    /// Nothing is written if the encoded part does not fit in the remaining space, or if the
+    /// format uses `LengthEncoding::U32` and the part is longer than `u32::MAX` bytes.
+    pub fn push<T: AsRef<[u8]>>(&mut self, part: T) -> Result<&mut Self, CapacityError> {
+        let part = part.as_ref();
+        self.check_fits(part.len())?;
+        self.write(self.format.lengths().encode(part.len()).as_ref())?;
+        self.write(part)?;
+        self.write(self.format.encode_suffix(part).as_ref())?;
+        self.len += 1;
+        Ok(self)
+    }
+
+    /// Get a view of the parts pushed so far.
+    pub fn as_buffer_ref(&self) -> BufferRef<'_> {
+        BufferRef::from_raw_parts(&self.data[..self.written], self.format, self.len, None)
+    }
+
+    /// Finish building, returning a view of the encoded bytes at the start of the slice.
+    pub fn finish(self) -> BufferRef<'a> {
+        let data: &'a [u8] = self.data;
+        BufferRef::from_raw_parts(&data[..self.written], self.format, self.len, None)
+    }
+
+    /// Check that a part of `len` bytes can be encoded in the remaining space.
+    fn check_fits(&self, len: usize) -> Result<(), CapacityError> {
+        if self.format.lengths() == LengthEncoding::U32 && len > u32::MAX as usize {
+            return Err(CapacityError::PartTooLong { len });
+        }
+        let required = self.format.encoded_len(len);
+        if required > self.remaining() {
+            return Err(CapacityError::InsufficientSpace { required, available: self.remaining() });
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), CapacityError> {
+        let available = self.remaining();
+        let target = self
+            .data
+            .get_mut(self.written..self.written + bytes.len())
+            .ok_or(CapacityError::InsufficientSpace { required: bytes.len(), available })?;
+        target.copy_from_slice(bytes);
+        self.written += bytes.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARTS: [&[u8]; 4] = [b"abc", b"", &[7; 300], b"de"];
+
+    fn formats() -> [Format; 4] {
+        [
+            Format::default(),
+            Format::new(LengthEncoding::U32).with_checksums(true).with_header(true),
+            Format::new(LengthEncoding::Varint).with_trailing_lengths(true),
+            Format::new(LengthEncoding::Varint)
+                .with_trailing_lengths(true)
+                .with_checksums(true)
+                .with_header(true),
+        ]
+    }
+
+    fn encoded_len(format: Format) -> usize {
+        format.header_len() + PARTS.iter().map(|part| format.encoded_len(part.len())).sum::<usize>()
+    }
+
+    #[test]
+    fn fills_slice_exactly() {
+        for &format in &formats() {
+            let mut data = [0xaa; 512];
+            let len = encoded_len(format);
+            let mut builder = SliceBuilder::with_format(&mut data[..len], format).unwrap();
+            assert!(builder.is_empty());
+            for part in &PARTS {
+                builder.push(part).unwrap();
+            }
+            assert_eq!(builder.len(), PARTS.len());
+            assert_eq!(builder.remaining(), 0);
+            assert_eq!(
+                builder.push(b"").err(),
+                Some(CapacityError::InsufficientSpace {
+                    required: format.encoded_len(0),
+                    available: 0
+                })
+            );
+            assert!(builder.as_buffer_ref().iter().eq(PARTS.iter().copied()));
+
+            let buffer = builder.finish();
+            assert_eq!(buffer.format(), format);
+            assert_eq!(buffer.len(), PARTS.len());
+            assert_eq!(buffer.validate(), Ok(()));
+            assert!(buffer.iter().eq(PARTS.iter().copied()));
+            assert!(buffer.iter().rev().eq(PARTS.iter().rev().copied()));
+            assert_eq!(data[len], 0xaa);
+            let decoded = BufferRef::from_slice_with_format(&data[..len], format).unwrap();
+            assert!(decoded.iter().eq(PARTS.iter().copied()));
+        }
+    }
+
+    #[test]
+    fn rejects_parts_that_do_not_fit() {
+        for &format in &formats() {
+            let mut data = [0; 512];
+            let len = encoded_len(format) - 1;
+            let mut builder = SliceBuilder::with_format(&mut data[..len], format).unwrap();
+            for part in &PARTS[..3] {
+                builder.push(part).unwrap();
+            }
+            let remaining = builder.remaining();
+            assert_eq!(
+                builder.push(PARTS[3]).err(),
+                Some(CapacityError::InsufficientSpace {
+                    required: format.encoded_len(2),
+                    available: remaining
+                })
+            );
+            // Nothing is written, so the parts pushed so far stay intact.
+            assert_eq!(builder.remaining(), remaining);
+            assert_eq!(builder.len(), 3);
+            assert!(builder.finish().iter().eq(PARTS[..3].iter().copied()));
+        }
+    }
+
+    #[test]
+    fn header_must_fit() {
+        let format = Format::default().with_header(true);
+        let mut data = [0; 8];
+        assert_eq!(
+            SliceBuilder::with_format(&mut data[..7], format).err(),
+            Some(CapacityError::InsufficientSpace { required: 8, available: 7 })
+        );
+        let builder = SliceBuilder::with_format(&mut data, format).unwrap();
+        assert_eq!(builder.remaining(), 0);
+        let buffer = builder.finish();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_bytes(), &Format::default().with_header(true).encode_header()[..]);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn rejects_long_u32_parts() {
+        let builder = SliceBuilder::with_format(&mut [], LengthEncoding::U32).unwrap();
+        let len = u32::MAX as usize + 1;
+        assert_eq!(builder.check_fits(len), Err(CapacityError::PartTooLong { len }));
+        let builder = SliceBuilder::with_format(&mut [], LengthEncoding::U64).unwrap();
+        assert_eq!(
+            builder.check_fits(len),
+            Err(CapacityError::InsufficientSpace { required: len + 8, available: 0 })
+        );
+    }
+}
@@ -0,0 +1,39 @@
+//! Fixtures shared by the tests of the buffer formats and the streaming readers and writers
+
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+
+#[cfg(feature = "std")]
+use crate::SplitBufferError;
+use crate::{Format, LengthEncoding};
+
+/// Parts around the boundaries of the one and two byte varint lengths
+pub(crate) fn parts() -> Vec<Vec<u8>> {
+    vec![b"abc".to_vec(), Vec::new(), vec![7; 127], vec![8; 128], vec![9; 300]]
+}
+
+/// Every combination of length encoding and options
+pub(crate) fn formats() -> Vec<Format> {
+    let mut formats = Vec::new();
+    for &lengths in &[LengthEncoding::U32, LengthEncoding::U64, LengthEncoding::Varint] {
+        for options in 0..16 {
+            formats.push(
+                Format::new(lengths)
+                    .with_trailing_lengths(options & 1 != 0)
+                    .with_checksums(options & 2 != 0)
+                    .with_index(options & 4 != 0)
+                    .with_header(options & 8 != 0),
+            );
+        }
+    }
+    formats
+}
+
+#[cfg(feature = "std")]
+/// Get the `SplitBufferError` wrapped in an `io::Error` of kind `InvalidData`.
+pub(crate) fn split_buffer_error(err: &io::Error) -> &SplitBufferError {
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    err.get_ref().and_then(|err| err.downcast_ref()).unwrap()
+}
@@ -0,0 +1,179 @@
+use std::io::{self, Write};
+use std::vec::Vec;
+
+use crate::{Format, LengthEncoding};
+
+/// Writes parts directly to an `io::Write` in the same framing as `Buffer`
+///
+/// Each part is written with a few small writes, so wrap unbuffered writers such as files and
+/// sockets in an `io::BufWriter`. The header, if the format has one, is written before the first
+/// part; call `finish` so that it is also written for buffers without parts.
+///
+/// Output can be read back with `Buffer::from_vec_with_format` or a `BufferReader`.
+#[derive(Debug)]
+pub struct BufferWriter<W: Write> {
+    inner: W,
+    format: Format,
+    totals: WriteTotals,
+    started: bool,
+}
+
+/// Number of parts and bytes written by a `BufferWriter`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteTotals {
+    /// Number of parts written.
+    pub parts: usize,
+    /// Number of encoded bytes written, including the header.
+    pub bytes: u64,
+}
+
+impl<W: Write> BufferWriter<W> {
+    /// Create a writer using the default `Format`.
+    pub fn new(inner: W) -> Self {
+        Self::with_format(inner, Format::default())
+    }
+
+    /// Create a writer using the given `Format`.
+    pub fn with_format<F: Into<Format>>(inner: W, format: F) -> Self {
+        BufferWriter {
+            inner,
+            format: format.into(),
+            totals: WriteTotals::default(),
+            started: false,
+        }
+    }
+
+    /// Get the `Format` parts are written in
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get the number of parts and bytes written so far.
+    pub fn totals(&self) -> WriteTotals {
+        self.totals
+    }
+
+    /// Write one part.
+    ///
+    /// Fails with `io::ErrorKind::InvalidInput` if the part is too long for the length encoding.
+    pub fn write_part(&mut self, part: &[u8]) -> io::Result<()> {
+        check_part_len(self.format, part.len())?;
+        self.start()?;
+        self.write(self.format.lengths().encode(part.len()).as_ref())?;
+        self.write(part)?;
+        self.write(self.format.encode_suffix(part).as_ref())?;
+        self.totals.parts += 1;
+        Ok(())
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Write the header if nothing has been written yet and flush, returning the underlying
+    /// writer and the totals.
+    pub fn finish(mut self) -> io::Result<(W, WriteTotals)> {
+        self.start()?;
+        self.flush()?;
+        Ok((self.inner, self.totals))
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    ///
+    /// Writing to it directly will corrupt the framing.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        let mut header = Vec::new();
+        self.format.write_header(&mut header);
+        self.write(&header)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        self.totals.bytes += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Check that a part of `len` bytes can be encoded in `format`.
+pub(crate) fn check_part_len(format: Format, len: usize) -> io::Result<()> {
+    if format.lengths() == LengthEncoding::U32 && len > u32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "part length must fit in `u32`"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{formats, parts};
+    use crate::Buffer;
+
+    #[test]
+    fn write_matches_build() {
+        let parts = parts();
+        for format in formats() {
+            let mut writer = BufferWriter::with_format(Vec::new(), format);
+            assert_eq!(writer.format(), format);
+            for part in &parts {
+                writer.write_part(part).unwrap();
+            }
+            let (bytes, totals) = writer.finish().unwrap();
+            let expected = Buffer::build_with_format(&parts, format);
+            assert_eq!(bytes, expected.as_bytes());
+            assert_eq!(totals, WriteTotals { parts: parts.len(), bytes: bytes.len() as u64 });
+            assert!(Buffer::from_vec_with_format(bytes, format)
+                .unwrap()
+                .iter()
+                .eq(expected.iter()));
+        }
+    }
+
+    #[test]
+    fn finish_writes_header_without_parts() {
+        let format = Format::new(LengthEncoding::Varint).with_header(true);
+        let (bytes, totals) = BufferWriter::with_format(Vec::new(), format).finish().unwrap();
+        assert_eq!(totals, WriteTotals { parts: 0, bytes: 8 });
+        assert_eq!(Buffer::from_vec(bytes).unwrap().format(), format);
+
+        let (bytes, totals) = BufferWriter::new(Vec::new()).finish().unwrap();
+        assert!(bytes.is_empty());
+        assert_eq!(totals, WriteTotals::default());
+    }
+
+    #[test]
+    fn header_written_once() {
+        let format = Format::default().with_header(true);
+        let mut writer = BufferWriter::with_format(Vec::new(), format);
+        writer.write_part(b"a").unwrap();
+        writer.write_part(b"b").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.totals().bytes, 8 + 9 + 9);
+        assert_eq!(writer.get_ref().len(), 8 + 9 + 9);
+    }
+
+    #[test]
+    fn check_part_len_rejects_long_u32_parts() {
+        assert!(check_part_len(LengthEncoding::U32.into(), u32::MAX as usize).is_ok());
+        assert!(check_part_len(LengthEncoding::U64.into(), usize::MAX).is_ok());
+        assert!(check_part_len(LengthEncoding::Varint.into(), usize::MAX).is_ok());
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(
+            check_part_len(LengthEncoding::U32.into(), u32::MAX as usize + 1).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}